@@ -1,5 +1,6 @@
 var map;
 var markers = new Map();
+var circles = new Map();
 var focusedMarker;
 var destinations = [];
 
@@ -28,20 +29,25 @@ function clickMarker(e) {
   name.scrollIntoView();
 }
 
-function addMarker(name, lng, lat) {
+function addMarker(name, lng, lat, radius) {
   var point = L.latLng(lat, lng);
   var marker = L.marker(point, {icon: apartIcon}).addTo(map);
   marker.bindTooltip(name);
   marker.on('click', clickMarker);
   markers.set(name, marker);
+  if (radius > 0) {
+    circles.set(name, L.circle(point, {radius: radius, className: 'uncertainty'}).addTo(map));
+  }
 }
 
 function clearMap() {
   destinations.forEach((marker) => marker.remove());
   markers.forEach((marker, _) => marker.remove());
+  circles.forEach((circle, _) => circle.remove());
 
   destinations = [];
   markers.clear();
+  circles.clear();
   focusedMarker = null;
 }
 
@@ -52,7 +58,11 @@ function fitMap() {
 }
 
 function focusMarker(name) {
+  // buildings that could not be geocoded have no marker
   focusedMarker = markers.get(name);
+  if (!focusedMarker) {
+    return;
+  }
   focusedMarker.setIcon(apartHoverIcon);
   focusedMarker.setZIndexOffset(10000);
   var bounds = [focusedMarker, ...destinations].map((marker) => marker.getLatLng());
@@ -60,7 +70,11 @@ function focusMarker(name) {
 }
 
 function unfocusMarker() {
+  if (!focusedMarker) {
+    return;
+  }
   focusedMarker.setIcon(apartIcon);
   focusedMarker.setZIndexOffset(0);
+  focusedMarker = null;
   fitMap();
 }
@@ -0,0 +1,587 @@
+use std::collections::HashMap;
+#[cfg(feature = "server")]
+use std::path::Path;
+
+#[cfg(feature = "server")]
+use encoding_rs::SHIFT_JIS;
+#[cfg(feature = "server")]
+use rusqlite::OptionalExtension;
+
+use crate::geocode::Precision;
+#[cfg(feature = "server")]
+use crate::{Error, backend::DB, geocode::Location};
+
+const KANJI_DIGITS: &str = "〇一二三四五六七八九";
+
+/// The 23 special wards, written without their prefecture.
+const TOKYO_WARDS: [&str; 23] = [
+    "千代田区",
+    "中央区",
+    "港区",
+    "新宿区",
+    "文京区",
+    "台東区",
+    "墨田区",
+    "江東区",
+    "品川区",
+    "目黒区",
+    "大田区",
+    "世田谷区",
+    "渋谷区",
+    "中野区",
+    "杉並区",
+    "豊島区",
+    "北区",
+    "荒川区",
+    "板橋区",
+    "練馬区",
+    "足立区",
+    "葛飾区",
+    "江戸川区",
+];
+
+/// Designated cities (政令指定都市), also written without their prefecture.
+const DESIGNATED_CITIES: [(&str, &str); 20] = [
+    ("札幌市", "北海道"),
+    ("仙台市", "宮城県"),
+    ("さいたま市", "埼玉県"),
+    ("千葉市", "千葉県"),
+    ("横浜市", "神奈川県"),
+    ("川崎市", "神奈川県"),
+    ("相模原市", "神奈川県"),
+    ("新潟市", "新潟県"),
+    ("静岡市", "静岡県"),
+    ("浜松市", "静岡県"),
+    ("名古屋市", "愛知県"),
+    ("京都市", "京都府"),
+    ("大阪市", "大阪府"),
+    ("堺市", "大阪府"),
+    ("神戸市", "兵庫県"),
+    ("岡山市", "岡山県"),
+    ("広島市", "広島県"),
+    ("北九州市", "福岡県"),
+    ("福岡市", "福岡県"),
+    ("熊本市", "熊本県"),
+];
+
+const PREFECTURES: [&str; 47] = [
+    "北海道",
+    "青森県",
+    "岩手県",
+    "宮城県",
+    "秋田県",
+    "山形県",
+    "福島県",
+    "茨城県",
+    "栃木県",
+    "群馬県",
+    "埼玉県",
+    "千葉県",
+    "東京都",
+    "神奈川県",
+    "新潟県",
+    "富山県",
+    "石川県",
+    "福井県",
+    "山梨県",
+    "長野県",
+    "岐阜県",
+    "静岡県",
+    "愛知県",
+    "三重県",
+    "滋賀県",
+    "京都府",
+    "大阪府",
+    "兵庫県",
+    "奈良県",
+    "和歌山県",
+    "鳥取県",
+    "島根県",
+    "岡山県",
+    "広島県",
+    "山口県",
+    "徳島県",
+    "香川県",
+    "愛媛県",
+    "高知県",
+    "福岡県",
+    "佐賀県",
+    "長崎県",
+    "熊本県",
+    "大分県",
+    "宮崎県",
+    "鹿児島県",
+    "沖縄県",
+];
+
+fn is_kanji_numeral(c: char) -> bool {
+    KANJI_DIGITS.contains(c) || matches!(c, '十' | '百' | '千')
+}
+
+/// Value of a kanji numeral, either counted (`二十三`) or positional (`二三`).
+fn kanji_number(numeral: &[char]) -> Option<u32> {
+    if numeral.is_empty() {
+        return None;
+    }
+    let mut total = 0;
+    let mut digits: Option<u32> = None;
+    for &c in numeral {
+        let unit = match c {
+            '十' => 10,
+            '百' => 100,
+            '千' => 1000,
+            c => {
+                let digit = KANJI_DIGITS.chars().position(|d| d == c)? as u32;
+                digits = Some(digits.unwrap_or(0) * 10 + digit);
+                continue;
+            }
+        };
+        total += digits.take().unwrap_or(1) * unit;
+    }
+    Some(total + digits.unwrap_or(0))
+}
+
+/// Length of the 丁目, 番地, 番 or 号 after a number, and what it becomes.
+fn marker(rest: &[char]) -> Option<(usize, &'static str)> {
+    match rest {
+        ['丁', '目', ..] | ['番', '地', ..] => Some((2, "-")),
+        // 一番町 is a name, while 三番5号 or 三番 alone are a block
+        ['番'] => Some((1, "-")),
+        ['番', next, ..] if next.is_ascii_digit() || is_kanji_numeral(*next) || *next == '-' => {
+            Some((1, "-"))
+        }
+        ['号', ..] => Some((1, "")),
+        _ => None,
+    }
+}
+
+/// Address starting with its prefecture, when it is missing or shortened (`東京港区`) but
+/// can be told from a ward of Tokyo, a designated city or a city named like its prefecture.
+fn prefecture(address: &str) -> Option<String> {
+    if PREFECTURES
+        .iter()
+        .any(|prefecture| address.starts_with(prefecture))
+    {
+        return None;
+    }
+    let ward = address.strip_prefix("東京").unwrap_or(address);
+    if TOKYO_WARDS.iter().any(|name| ward.starts_with(name)) {
+        return Some(format!("東京都{ward}"));
+    }
+    DESIGNATED_CITIES
+        .iter()
+        .find(|(city, _)| address.starts_with(city))
+        .map(|(_, prefecture)| format!("{prefecture}{address}"))
+        .or_else(|| {
+            // 長野市 is in 長野県, but 長野原町 is not
+            PREFECTURES.iter().find_map(|prefecture| {
+                let short = prefecture.strip_suffix(['都', '府', '県'])?;
+                let rest = address.strip_prefix(short)?;
+                rest.starts_with('市')
+                    .then(|| format!("{prefecture}{address}"))
+            })
+        })
+}
+
+/// The canonical spelling of an address, keying the caches and sent to geocoders.
+///
+/// Digits and dashes are half-width and whitespace is dropped. Numbers written in kanji before
+/// 丁目, 番地, 番 or 号 become digits, and those markers dashes, so that `六本木２`, `六本木2`
+/// and `六本木二丁目` are the same, as are `六本木二丁目3番地1号` and `六本木2-3-1`. The
+/// prefecture is added where it can be told.
+pub fn normalize(address: &str) -> String {
+    let mut chars: Vec<char> = vec![];
+    for c in address.chars() {
+        let c = match c {
+            '－' | '−' | '‐' | '‑' | '–' | '—' | '―' => '-',
+            // the prolonged sound mark is only a dash after a number
+            'ー' if chars.last().is_some_and(|c| c.is_ascii_digit()) => '-',
+            // full-width ASCII
+            '！'..='～' => char::from_u32(c as u32 - '！' as u32 + '!' as u32).unwrap_or(c),
+            'ヶ' | 'ヵ' => 'ケ',
+            c if c.is_whitespace() => continue,
+            c => c,
+        };
+        chars.push(c);
+    }
+
+    let mut normalized = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let digits = chars[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+        if digits > 0 {
+            normalized.extend(&chars[i..i + digits]);
+            i += digits;
+        } else {
+            let numeral = chars[i..]
+                .iter()
+                .take_while(|c| is_kanji_numeral(**c))
+                .count();
+            let number = kanji_number(&chars[i..i + numeral]);
+            match (number, marker(&chars[i + numeral..])) {
+                (Some(number), Some(_)) => {
+                    normalized += &number.to_string();
+                    i += numeral;
+                }
+                _ => {
+                    normalized.push(chars[i]);
+                    i += 1;
+                    continue;
+                }
+            }
+        }
+        if let Some((length, replacement)) = marker(&chars[i..]) {
+            normalized += replacement;
+            i += length;
+        }
+    }
+
+    // dashes only separate numbers
+    let mut key = String::new();
+    let mut rest = normalized.chars().peekable();
+    while let Some(c) = rest.next() {
+        let separates = key.ends_with(|c: char| c.is_ascii_digit())
+            && rest.peek().is_some_and(|c| c.is_ascii_digit());
+        if c != '-' || separates {
+            key.push(c);
+        }
+    }
+
+    prefecture(&key).unwrap_or(key)
+}
+
+/// The finest level named by a normalized address: a chome or town after the city, then a block
+/// and a building number separated by dashes.
+pub fn precision(address: &str) -> Precision {
+    let rest = PREFECTURES
+        .iter()
+        .find_map(|prefecture| address.strip_prefix(prefecture))
+        .unwrap_or(address);
+    if rest.is_empty() {
+        return Precision::Prefecture;
+    }
+    // the town starts after the ward of a city, or else after the city, town or village, whose
+    // name may itself start with 市 or 町 (市川市, 町田市)
+    let town = ['区', '市', '町', '村']
+        .into_iter()
+        .find_map(|suffix| {
+            let (start, _) = rest.char_indices().skip(1).find(|(_, c)| *c == suffix)?;
+            Some(&rest[start + suffix.len_utf8()..])
+        })
+        .unwrap_or(rest);
+    match town.matches('-').count() {
+        _ if town.is_empty() => Precision::City,
+        0 => Precision::Chome,
+        1 => Precision::Block,
+        _ => Precision::Exact,
+    }
+}
+
+/// Fields of a CSV line, whose values are quoted but never contain commas.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+fn fields(line: &str) -> Vec<String> {
+    line.trim_start_matches('\u{feff}')
+        .split(',')
+        .map(|field| field.trim().trim_matches('"').to_string())
+        .collect()
+}
+
+/// A place of the address reference data, by its normalized address.
+#[derive(Clone, PartialEq, Debug)]
+struct ReferencePlace {
+    key: String,
+    coordinates: (f64, f64),
+    /// Whether the coordinates are the average of the blocks of a chome, rather than given.
+    averaged: bool,
+}
+
+/// The places of a CSV of the 位置参照情報, at the 大字・町丁目 (chome) or the 街区 (block)
+/// level, along with the chome of the blocks.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+fn reference_places(text: &str) -> Result<Vec<ReferencePlace>, String> {
+    let mut lines = text.lines();
+    let header = fields(lines.next().unwrap_or_default());
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|field| field == name)
+            .ok_or(format!("no {name} column"))
+    };
+    let prefecture = column("都道府県名")?;
+    let city = column("市区町村名")?;
+    let town = column("大字町丁目名").or_else(|_| column("大字・丁目名"))?;
+    let block = column("街区符号・地番").ok();
+    let lat = column("緯度")?;
+    let lng = column("経度")?;
+
+    let mut places = vec![];
+    // in order of appearance, and indexed by place as block-level files have many rows
+    let mut towns: Vec<(String, (f64, f64, usize))> = vec![];
+    let mut town_indices: HashMap<String, usize> = HashMap::new();
+    for line in lines {
+        let fields = fields(line);
+        let get = |i: usize| fields.get(i).map(String::as_str).unwrap_or_default();
+        let (Ok(lat), Ok(lng)) = (get(lat).parse::<f64>(), get(lng).parse::<f64>()) else {
+            continue;
+        };
+        let place = normalize(&format!("{}{}{}", get(prefecture), get(city), get(town)));
+        let key = match block {
+            Some(block) => {
+                let key = normalize(&format!("{place}-{}", get(block)));
+                let k = *town_indices.entry(place.clone()).or_insert_with(|| {
+                    towns.push((place, (0.0, 0.0, 0)));
+                    towns.len() - 1
+                });
+                let town = &mut towns[k].1;
+                *town = (town.0 + lng, town.1 + lat, town.2 + 1);
+                key
+            }
+            None => place,
+        };
+        places.push(ReferencePlace {
+            key,
+            coordinates: (lng, lat),
+            averaged: false,
+        });
+    }
+    places.extend(
+        towns
+            .into_iter()
+            .map(|(key, (lng, lat, blocks))| ReferencePlace {
+                key,
+                coordinates: (lng / blocks as f64, lat / blocks as f64),
+                averaged: true,
+            }),
+    );
+    Ok(places)
+}
+
+/// Stores `places`, where given coordinates replace averaged ones whatever the import order,
+/// returning the number of places stored.
+#[cfg(feature = "server")]
+fn store_places(db: &rusqlite::Connection, places: &[ReferencePlace]) -> Result<usize, Error> {
+    let mut replace =
+        db.prepare("INSERT OR REPLACE INTO reference_addresses VALUES (?1, ?2, ?3)")?;
+    let mut ignore = db.prepare("INSERT OR IGNORE INTO reference_addresses VALUES (?1, ?2, ?3)")?;
+    let mut count = 0;
+    for place in places {
+        let insert = if place.averaged {
+            &mut ignore
+        } else {
+            &mut replace
+        };
+        count += insert.execute((&place.key, place.coordinates.0, place.coordinates.1))?;
+    }
+    Ok(count)
+}
+
+/// Loads a CSV of the 位置参照情報 (address reference data) of the MLIT into the database,
+/// returning the number of places stored.
+///
+/// Both the 大字・町丁目 (chome) and the 街区 (block) levels are understood, in Shift_JIS as
+/// distributed or converted to UTF-8. Chome of block-level files get the average coordinates of
+/// their blocks, unless a chome-level file provides them, before or after.
+#[cfg(feature = "server")]
+pub fn import(path: &Path) -> Result<usize, Error> {
+    let text = match String::from_utf8(std::fs::read(path)?) {
+        Ok(text) => text,
+        Err(error) => SHIFT_JIS.decode(error.as_bytes()).0.into_owned(),
+    };
+    let places = reference_places(&text)
+        .map_err(|error| Error::Misc(format!("{error} in {}", path.display())))?;
+    DB.with(|db| {
+        let transaction = db.unchecked_transaction()?;
+        let count = store_places(&transaction, &places)?;
+        transaction.commit()?;
+        Ok(count)
+    })
+}
+
+/// The places that `address` may be in, from the most precise, each ending where a number
+/// does, so that 六本木2-31 is not taken for block 3.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+fn candidates(address: &str) -> impl Iterator<Item = &str> {
+    address
+        .char_indices()
+        .rev()
+        .map(|(start, c)| start + c.len_utf8())
+        .filter(|&end| !address[end..].starts_with(|c: char| c.is_ascii_digit()))
+        .map(|end| &address[..end])
+}
+
+/// Location of the most precise imported place that `address` starts with.
+#[cfg(feature = "server")]
+pub fn locate(address: &str) -> Result<Option<Location>, Error> {
+    let address = normalize(address);
+    DB.with(|db| {
+        let mut query =
+            db.prepare_cached("SELECT lng, lat FROM reference_addresses WHERE key = ?1")?;
+        for place in candidates(&address) {
+            let coordinates = query
+                .query_row([place], |row| Ok((row.get(0)?, row.get(1)?)))
+                .optional()?;
+            if let Some(coordinates) = coordinates {
+                return Ok(Some(Location {
+                    coordinates,
+                    precision: precision(place),
+                }));
+            }
+        }
+        Ok(None)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_chome() {
+        for address in [
+            "東京都港区六本木２",
+            "東京都港区六本木2",
+            "東京都港区 六本木二丁目",
+        ] {
+            assert_eq!(normalize(address), "東京都港区六本木2");
+        }
+        assert_eq!(normalize("新宿区西新宿二十三丁目"), "東京都新宿区西新宿23");
+    }
+
+    #[test]
+    fn normalizes_blocks() {
+        for address in [
+            "東京都港区六本木２－３－１",
+            "東京都港区六本木2丁目3番地1号",
+            "港区六本木二丁目三番一号",
+            "東京港区六本木2ー3ー1",
+        ] {
+            assert_eq!(normalize(address), "東京都港区六本木2-3-1");
+        }
+    }
+
+    #[test]
+    fn keeps_numerals_of_names() {
+        assert_eq!(normalize("千代田区一番町"), "東京都千代田区一番町");
+        assert_eq!(normalize("中央区八丁堀三丁目"), "東京都中央区八丁堀3");
+    }
+
+    #[test]
+    fn completes_prefectures() {
+        assert_eq!(normalize("京都市左京区"), "京都府京都市左京区");
+        assert_eq!(normalize("大阪市北区梅田一丁目"), "大阪府大阪市北区梅田1");
+        assert_eq!(normalize("長野市"), "長野県長野市");
+        assert_eq!(normalize("群馬県吾妻郡長野原町"), "群馬県吾妻郡長野原町");
+    }
+
+    #[test]
+    fn tells_precision() {
+        for (address, expected) in [
+            ("東京都", Precision::Prefecture),
+            ("東京都港区", Precision::City),
+            ("東京都町田市", Precision::City),
+            ("東京都港区六本木2", Precision::Chome),
+            ("東京都町田市原町田6", Precision::Chome),
+            ("東京都西多摩郡奥多摩町氷川", Precision::Chome),
+            ("東京都港区六本木2-31", Precision::Block),
+            ("東京都港区六本木2-31-5", Precision::Exact),
+        ] {
+            assert_eq!(precision(address), expected, "{address}");
+        }
+    }
+
+    const CHOME_CSV: &str = "都道府県コード,都道府県名,市区町村コード,市区町村名,大字町丁目コード,大字町丁目名,緯度,経度,原典資料コード,大字・字・丁目区分コード
+13,東京都,13103,港区,131030021002,六本木二丁目,35.664,139.737,1,3
+13,東京都,13103,港区,131030021003,六本木三丁目,35.663,139.734,1,3";
+
+    const BLOCK_CSV: &str = "\u{feff}\"都道府県名\",\"市区町村名\",\"大字・丁目名\",\"小字・通称名\",\"街区符号・地番\",\"座標系番号\",\"Ｘ座標\",\"Ｙ座標\",\"緯度\",\"経度\"
+\"東京都\",\"港区\",\"六本木二丁目\",\"\",\"3\",\"9\",\"0\",\"0\",\"35.665\",\"139.736\"
+\"東京都\",\"港区\",\"六本木二丁目\",\"\",\"31\",\"9\",\"0\",\"0\",\"35.667\",\"139.740\"
+\"東京都\",\"港区\",\"六本木二丁目\",\"\",\"x\",\"9\",\"0\",\"0\",\"\",\"\"";
+
+    #[test]
+    fn reads_chome_places() {
+        let places = reference_places(CHOME_CSV).unwrap();
+        assert_eq!(
+            places,
+            [
+                ReferencePlace {
+                    key: "東京都港区六本木2".to_string(),
+                    coordinates: (139.737, 35.664),
+                    averaged: false,
+                },
+                ReferencePlace {
+                    key: "東京都港区六本木3".to_string(),
+                    coordinates: (139.734, 35.663),
+                    averaged: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn averages_blocks_into_chome() {
+        let places = reference_places(BLOCK_CSV).unwrap();
+        let keys = places
+            .iter()
+            .map(|place| place.key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            [
+                "東京都港区六本木2-3",
+                "東京都港区六本木2-31",
+                "東京都港区六本木2"
+            ]
+        );
+        let chome = &places[2];
+        assert!(chome.averaged);
+        assert!((chome.coordinates.0 - 139.738).abs() < 1e-9);
+        assert!((chome.coordinates.1 - 35.666).abs() < 1e-9);
+    }
+
+    #[test]
+    fn requires_coordinates() {
+        let csv = CHOME_CSV.replace("緯度", "北緯");
+        assert_eq!(reference_places(&csv), Err("no 緯度 column".to_string()));
+    }
+
+    #[test]
+    fn lists_candidates_at_number_ends() {
+        let candidates = candidates("東京都港区六本木2-31-5").collect::<Vec<_>>();
+        assert_eq!(candidates[0], "東京都港区六本木2-31-5");
+        assert!(candidates.contains(&"東京都港区六本木2-31"));
+        assert!(candidates.contains(&"東京都港区六本木2"));
+        assert!(!candidates.contains(&"東京都港区六本木2-3"));
+        assert!(!candidates.contains(&"東京都港区六本木2-"));
+        assert!(candidates.contains(&"東京都港区"));
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn prefers_given_chome_whatever_the_order() {
+        let chome = reference_places(CHOME_CSV).unwrap();
+        let blocks = reference_places(BLOCK_CSV).unwrap();
+        for files in [[&chome, &blocks], [&blocks, &chome]] {
+            let db = rusqlite::Connection::open_in_memory().unwrap();
+            db.execute(
+                "CREATE TABLE reference_addresses (
+                    key TEXT PRIMARY KEY,
+                    lng REAL NOT NULL,
+                    lat REAL NOT NULL)",
+                [],
+            )
+            .unwrap();
+            for places in files {
+                store_places(&db, places).unwrap();
+            }
+            let coordinates: (f64, f64) = db
+                .query_row(
+                    "SELECT lng, lat FROM reference_addresses WHERE key = '東京都港区六本木2'",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap();
+            assert_eq!(coordinates, (139.737, 35.664));
+        }
+    }
+}
@@ -1,6 +1,16 @@
 use dioxus::prelude::*;
 
-use crate::{Criterion, SUUMOURL, TransportationMode};
+#[cfg(feature = "server")]
+use crate::SUUMOURL;
+#[cfg(feature = "server")]
+use crate::addresses::{self, normalize};
+#[cfg(feature = "server")]
+use crate::{Apartment, ListingStatus};
+use crate::{
+    ApartmentDetails, Building, Criterion, SavedSearch, TransportationMode,
+    geocode::{GeocoderSettings, Location},
+    scrape::{ScrapeConfig, ScrapedPage},
+};
 
 #[cfg(feature = "server")]
 const ADDRESS: &str = "東京都渋谷区渋谷1-3-7";
@@ -9,53 +19,164 @@ const ADDRESS: &str = "東京都渋谷区渋谷1-3-7";
 const TIMEOUT: usize = 20;
 #[cfg(feature = "server")]
 const DESTCOLOR: &str = "#c92a2a";
+/// Addresses that were not found are tried again after this many days, in case the geocoder
+/// learnt about them.
+#[cfg(feature = "server")]
+const UNRESOLVED_DAYS: i64 = 30;
+
+#[cfg(feature = "server")]
+use rusqlite::OptionalExtension;
 
 #[cfg(feature = "server")]
 thread_local! {
     pub static DB: rusqlite::Connection = {
         let conn = rusqlite::Connection::open("data.db").expect("Failed to open database");
-
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS buildings (
-                address TEXT PRIMARY KEY,
-                lat REAL,
-                lng REAL,
-                reachable INTEGER,
-                time INTEGER
-            );
-            CREATE TABLE IF NOT EXISTS credentials (
-                app_id TEXT NOT NULL,
-                key TEXT NOT NULL
-            );
-            CREATE TABLE IF NOT EXISTS criteria (
-                address TEXT NOT NULL,
-                mode TEXT NOT NULL,
-                time INTEGER,
-                color TEXT NOT NULL);
-            CREATE TABLE IF NOT EXISTS cycling(
-                origin TEXT NOT NULL,
-                destination TEXT NOT NULL,
-                time INTEGER);
-            CREATE TABLE IF NOT EXISTS walking (
-                origin TEXT NOT NULL,
-                destination TEXT NOT NULL,
-                time INTEGER);
-            CREATE TABLE IF NOT EXISTS driving (
-                origin TEXT NOT NULL,
-                destination TEXT NOT NULL,
-                time INTEGER);
-            CREATE TABLE IF NOT EXISTS public (
-                origin TEXT NOT NULL,
-                destination TEXT NOT NULL,
-                time INTEGER);
-            CREATE TABLE IF NOT EXISTS config (
-                id INTEGER PRIMARY KEY,
-                url TEXT NOT NULL);").unwrap();
-
+        init_database(&conn).expect("Failed to initialize database");
         conn
     };
 }
 
+/// Creates the tables, and migrates the ones of older versions.
+#[cfg(feature = "server")]
+fn init_database(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS buildings (
+            address TEXT PRIMARY KEY,
+            lat REAL,
+            lng REAL,
+            reachable INTEGER,
+            time INTEGER,
+            provider TEXT,
+            precision TEXT
+        );
+        CREATE TABLE IF NOT EXISTS credentials (
+            app_id TEXT NOT NULL,
+            key TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS criteria (
+            address TEXT NOT NULL,
+            mode TEXT NOT NULL,
+            time INTEGER,
+            color TEXT NOT NULL);
+        CREATE TABLE IF NOT EXISTS cycling(
+            origin TEXT NOT NULL,
+            destination TEXT NOT NULL,
+            time INTEGER);
+        CREATE TABLE IF NOT EXISTS walking (
+            origin TEXT NOT NULL,
+            destination TEXT NOT NULL,
+            time INTEGER);
+        CREATE TABLE IF NOT EXISTS driving (
+            origin TEXT NOT NULL,
+            destination TEXT NOT NULL,
+            time INTEGER);
+        CREATE TABLE IF NOT EXISTS public (
+            origin TEXT NOT NULL,
+            destination TEXT NOT NULL,
+            time INTEGER);
+        CREATE TABLE IF NOT EXISTS config (
+            id INTEGER PRIMARY KEY,
+            url TEXT NOT NULL);
+        CREATE TABLE IF NOT EXISTS saved_searches (
+            position INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            url TEXT NOT NULL,
+            enabled INTEGER NOT NULL);
+        CREATE TABLE IF NOT EXISTS listed_buildings (
+            name TEXT NOT NULL,
+            address TEXT NOT NULL,
+            data TEXT NOT NULL,
+            PRIMARY KEY (name, address));
+        CREATE TABLE IF NOT EXISTS listings (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            address TEXT NOT NULL,
+            data TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            first_seen INTEGER NOT NULL,
+            last_seen INTEGER NOT NULL);
+        CREATE TABLE IF NOT EXISTS searches (
+            time INTEGER PRIMARY KEY);
+        CREATE TABLE IF NOT EXISTS scraped_pages (
+            url TEXT NOT NULL,
+            page INTEGER NOT NULL,
+            data TEXT NOT NULL,
+            PRIMARY KEY (url, page));
+        CREATE TABLE IF NOT EXISTS details (
+            id INTEGER PRIMARY KEY,
+            data TEXT NOT NULL);
+        CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL);
+        CREATE TABLE IF NOT EXISTS unresolved_addresses (
+            address TEXT NOT NULL,
+            provider TEXT NOT NULL,
+            time INTEGER NOT NULL,
+            PRIMARY KEY (address, provider));
+        CREATE TABLE IF NOT EXISTS reference_addresses (
+            key TEXT PRIMARY KEY,
+            lng REAL NOT NULL,
+            lat REAL NOT NULL);",
+    )?;
+
+    // columns added since the first version
+    let has_provider = conn.prepare("SELECT provider FROM buildings").is_ok();
+    if !has_provider {
+        conn.execute("ALTER TABLE buildings ADD COLUMN provider TEXT", [])?;
+    }
+    let has_precision = conn.prepare("SELECT precision FROM buildings").is_ok();
+    if !has_precision {
+        conn.execute("ALTER TABLE buildings ADD COLUMN precision TEXT", [])?;
+    }
+
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version < 1 {
+        normalize_addresses(conn)?;
+    }
+    Ok(())
+}
+
+/// Merges the cached coordinates and travel times of addresses spelled differently, keeping
+/// the first row of each normalized address.
+#[cfg(feature = "server")]
+fn normalize_addresses(db: &rusqlite::Connection) -> rusqlite::Result<()> {
+    let transaction = db.unchecked_transaction()?;
+    let addresses = transaction
+        .prepare("SELECT address FROM buildings")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for address in addresses {
+        let normalized = normalize(&address);
+        if normalized != address {
+            transaction.execute(
+                "INSERT OR IGNORE INTO buildings (address, lat, lng, reachable, time, provider)
+                 SELECT ?2, lat, lng, reachable, time, provider FROM buildings WHERE address = ?1",
+                (&address, &normalized),
+            )?;
+            transaction.execute("DELETE FROM buildings WHERE address = ?1", [&address])?;
+        }
+    }
+    for table in ["cycling", "walking", "driving", "public"] {
+        for column in ["origin", "destination"] {
+            let addresses = transaction
+                .prepare(&format!("SELECT DISTINCT {column} FROM {table}"))?
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            for address in addresses {
+                let normalized = normalize(&address);
+                if normalized != address {
+                    transaction.execute(
+                        &format!("UPDATE {table} SET {column} = ?2 WHERE {column} = ?1"),
+                        (&address, &normalized),
+                    )?;
+                }
+            }
+        }
+    }
+    transaction.execute_batch("PRAGMA user_version = 1")?;
+    transaction.commit()
+}
+
 #[server]
 pub async fn save_credentials(app_id: String, api_key: String) -> Result<()> {
     DB.with(|db| db.execute("DELETE FROM credentials", []))?;
@@ -74,32 +195,80 @@ pub async fn get_credentials() -> Result<(String, String)> {
     })?)
 }
 
+/// Location of `address`, as precise as its address for rows older than precisions.
 #[server]
-pub async fn get_coords(address: String) -> Result<(f64, f64)> {
-    Ok(DB.with(|db| {
+pub async fn get_coords(address: String) -> Result<Location> {
+    let address = normalize(&address);
+    let (coordinates, precision): ((f64, f64), Option<String>) = DB.with(|db| {
         db.query_row(
-            "SELECT lat, lng FROM buildings WHERE address = ?1",
-            [address],
+            "SELECT lat, lng, precision FROM buildings WHERE address = ?1",
+            [&address],
             |row| {
                 let lat: f64 = row.get(0)?;
                 let lng: f64 = row.get(1)?;
-                Ok((lng, lat))
+                Ok(((lng, lat), row.get(2)?))
             },
         )
-    })?)
+    })?;
+    let precision = match precision {
+        Some(precision) => serde_json::from_str(&precision)?,
+        None => addresses::precision(&address),
+    };
+    Ok(Location {
+        coordinates,
+        precision,
+    })
 }
 
 #[server]
-pub async fn set_coords(address: String, lng: f64, lat: f64) -> Result<()> {
+pub async fn set_coords(address: String, location: Location, provider: String) -> Result<()> {
+    let (lng, lat) = location.coordinates;
+    let precision = serde_json::to_string(&location.precision)?;
     DB.with(|db| {
         db.execute(
-            "INSERT INTO buildings VALUES (?1, ?2, ?3, NULL, NULL) ON CONFLICT DO NOTHING",
-            (address, lat, lng),
+            "INSERT INTO buildings (address, lat, lng, provider, precision)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT DO NOTHING",
+            (normalize(&address), lat, lng, provider, precision),
         )
     })?;
     Ok(())
 }
 
+/// Whether `provider` did not find `address` in the last `UNRESOLVED_DAYS`.
+#[server]
+pub async fn is_unresolved(address: String, provider: String) -> Result<bool> {
+    let since = chrono::Utc::now().timestamp() - UNRESOLVED_DAYS * 24 * 60 * 60;
+    let time: Option<i64> = DB.with(|db| {
+        db.query_row(
+            "SELECT time FROM unresolved_addresses WHERE address = ?1 AND provider = ?2",
+            (normalize(&address), provider),
+            |row| row.get(0),
+        )
+        .optional()
+    })?;
+    Ok(time.is_some_and(|time| time >= since))
+}
+
+#[server]
+pub async fn set_unresolved(address: String, provider: String) -> Result<()> {
+    let now = chrono::Utc::now().timestamp();
+    DB.with(|db| {
+        db.execute(
+            "INSERT INTO unresolved_addresses VALUES (?1, ?2, ?3)
+             ON CONFLICT DO UPDATE SET time = ?3",
+            (normalize(&address), provider, now),
+        )
+    })?;
+    Ok(())
+}
+
+/// Location of `address` in the imported address reference data, if any.
+#[server]
+pub async fn locate_offline(address: String) -> Result<Option<Location>> {
+    Ok(crate::addresses::locate(&address)?)
+}
+
 #[server]
 pub async fn get_time(
     origin: String,
@@ -115,7 +284,13 @@ pub async fn get_time(
             TransportationMode::Public => "public",
         }
     );
-    Ok(DB.with(|db| db.query_row(&query, (origin, destination), |row| row.get(0)))?)
+    Ok(DB.with(|db| {
+        db.query_row(
+            &query,
+            (normalize(&origin), normalize(&destination)),
+            |row| row.get(0),
+        )
+    })?)
 }
 
 #[server]
@@ -134,7 +309,7 @@ pub async fn set_time(
             TransportationMode::Public => "public",
         }
     );
-    DB.with(|db| db.execute(&query, (origin, destination, time)))?;
+    DB.with(|db| db.execute(&query, (normalize(&origin), normalize(&destination), time)))?;
     Ok(())
 }
 
@@ -142,7 +317,7 @@ pub async fn set_time(
 pub async fn get_criteria() -> Result<Vec<Criterion>> {
     let mut criteria: Vec<Criterion> = DB.with(|db| {
         let mut query = db.prepare("SELECT * FROM criteria")?;
-        let criteria = query
+        query
             .query_map([], move |row| {
                 let address: String = row.get(0)?;
                 let mode: String = row.get(1)?;
@@ -159,8 +334,7 @@ pub async fn get_criteria() -> Result<Vec<Criterion>> {
                     location,
                 })
             })?
-            .collect::<Result<Vec<Criterion>, _>>();
-        criteria
+            .collect::<Result<Vec<Criterion>, _>>()
     })?;
 
     if criteria.is_empty() {
@@ -198,21 +372,467 @@ pub async fn set_criteria(criteria: Vec<Criterion>) -> Result<()> {
     Ok(())
 }
 
+/// Scrapes one result page of a search.
+#[server]
+pub async fn scrape_live_page(
+    url: String,
+    page: usize,
+    config: ScrapeConfig,
+) -> Result<ScrapedPage> {
+    let page_url = supported_source(&url)?.page_url(&url, page);
+    let html = crate::scrape::fetch(&page_url, &config).await?;
+    Ok(crate::scrape::parse_live_page(&url, &html, page)?)
+}
+
+/// Reads and parses one page of saved results, which the browser has no access to.
+///
+/// Only the pages under the `SAVED_PAGES` directory, `pages` by default, can be read.
+#[server]
+pub async fn scrape_saved_page(path: std::path::PathBuf, page: usize) -> Result<ScrapedPage> {
+    let dir = std::env::var_os("SAVED_PAGES").unwrap_or_else(|| "pages".into());
+    let dir = std::path::Path::new(&dir);
+    Ok(crate::scrape::read_saved_page(dir, &path, page)?)
+}
+
+/// The portal of `url`, which the server fetches nothing outside of.
+#[cfg(feature = "server")]
+fn supported_source(
+    url: &str,
+) -> std::result::Result<&'static dyn crate::sources::ListingSource, crate::Error> {
+    crate::sources::supported_source(url)
+        .ok_or_else(|| crate::Error::Scrape(format!("{url} is not on a supported portal")))
+}
+
+/// Saves a scraped and geocoded page, for an interrupted search to resume from.
+#[server]
+pub async fn save_scraped_page(url: String, page: usize, scraped: ScrapedPage) -> Result<()> {
+    let data = serde_json::to_string(&scraped)?;
+    DB.with(|db| {
+        db.execute(
+            "INSERT INTO scraped_pages VALUES (?1, ?2, ?3) ON CONFLICT DO UPDATE SET data = ?3",
+            (url, page as i64, data),
+        )
+    })?;
+    Ok(())
+}
+
+/// Pages saved so far by the interrupted search at `url`.
+#[server]
+pub async fn get_scraped_pages(url: String) -> Result<Vec<(usize, ScrapedPage)>> {
+    let rows: Vec<(i64, String)> = DB.with(|db| {
+        let mut query = db.prepare("SELECT page, data FROM scraped_pages WHERE url = ?1")?;
+        query
+            .query_map([url], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+    let mut pages = vec![];
+    for (page, data) in rows {
+        pages.push((page as usize, serde_json::from_str(&data)?));
+    }
+    Ok(pages)
+}
+
+/// Whether a search was interrupted, and can be resumed.
+#[server]
+pub async fn has_scraped_pages() -> Result<bool> {
+    let exists = DB.with(|db| {
+        db.query_row("SELECT EXISTS (SELECT 1 FROM scraped_pages)", [], |row| {
+            row.get(0)
+        })
+    })?;
+    Ok(exists)
+}
+
+/// Forgets the saved pages, once a search completes or another one starts over.
+#[server]
+pub async fn clear_scraped_pages() -> Result<()> {
+    DB.with(|db| db.execute("DELETE FROM scraped_pages", []))?;
+    Ok(())
+}
+
+/// Scrapes and saves the detail page of an apartment.
+#[server]
+pub async fn get_details(id: u64, url: String, config: ScrapeConfig) -> Result<ApartmentDetails> {
+    let source = supported_source(&url)?;
+    let html = crate::scrape::fetch(&url, &config).await?;
+    let details = source.parse_details(&html)?;
+    save_details(id, details.clone()).await?;
+    Ok(details)
+}
+
+/// Saves the details of an apartment, which its listing is loaded with from then on.
+#[server]
+pub async fn save_details(id: u64, details: ApartmentDetails) -> Result<()> {
+    let data = serde_json::to_string(&details)?;
+    DB.with(|db| {
+        db.execute(
+            "INSERT INTO details VALUES (?1, ?2) ON CONFLICT DO UPDATE SET data = ?2",
+            (id as i64, data),
+        )
+    })?;
+    Ok(())
+}
+
 #[server]
-pub async fn get_suumo_url() -> Result<String> {
-    let url = DB
-        .with(|db| db.query_row("SELECT url FROM config", [], |row| row.get(0)))
-        .unwrap_or(SUUMOURL.to_string());
-    Ok(url)
+pub async fn get_geocoder_settings() -> Result<GeocoderSettings> {
+    let settings: Option<String> = DB.with(|db| {
+        db.query_row(
+            "SELECT value FROM settings WHERE key = 'geocoder'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()
+    })?;
+    match settings {
+        Some(settings) => Ok(serde_json::from_str(&settings)?),
+        None => Ok(GeocoderSettings::default()),
+    }
 }
 
 #[server]
-pub async fn set_suumo_url(url: String) -> Result<()> {
+pub async fn set_geocoder_settings(settings: GeocoderSettings) -> Result<()> {
+    let value = serde_json::to_string(&settings)?;
     DB.with(|db| {
         db.execute(
-            "INSERT INTO config VALUES (0, ?1) ON CONFLICT DO UPDATE SET url = ?1",
-            [url],
+            "INSERT INTO settings VALUES ('geocoder', ?1) ON CONFLICT DO UPDATE SET value = ?1",
+            [value],
         )
     })?;
     Ok(())
 }
+
+/// Saved searches, starting from the single URL of older databases.
+#[server]
+pub async fn get_saved_searches() -> Result<Vec<SavedSearch>> {
+    let mut searches: Vec<SavedSearch> = DB.with(|db| {
+        let mut query =
+            db.prepare("SELECT name, url, enabled FROM saved_searches ORDER BY position")?;
+        query
+            .query_map([], |row| {
+                Ok(SavedSearch {
+                    name: row.get(0)?,
+                    url: row.get(1)?,
+                    enabled: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+
+    if searches.is_empty() {
+        let url = DB
+            .with(|db| db.query_row("SELECT url FROM config", [], |row| row.get(0)))
+            .unwrap_or(SUUMOURL.to_string());
+        searches = vec![SavedSearch {
+            name: "Default".to_string(),
+            url,
+            enabled: true,
+        }]
+    }
+
+    Ok(searches)
+}
+
+#[server]
+pub async fn set_saved_searches(searches: Vec<SavedSearch>) -> Result<()> {
+    DB.with(|db| {
+        let transaction = db.unchecked_transaction()?;
+        transaction.execute("DELETE FROM saved_searches", [])?;
+        for (position, search) in searches.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO saved_searches VALUES (?1, ?2, ?3, ?4)",
+                (position as i64, &search.name, &search.url, search.enabled),
+            )?;
+        }
+        transaction.commit()
+    })?;
+    Ok(())
+}
+
+/// Listings of the search at `last`, followed by the ones of the search at `previous` that
+/// have disappeared since.
+#[cfg(feature = "server")]
+fn load_listings(
+    db: &rusqlite::Connection,
+    last: i64,
+    previous: Option<i64>,
+) -> std::result::Result<Vec<Building>, crate::Error> {
+    let mut query = db.prepare(
+        "SELECT listed_buildings.data, listings.data, last_seen, details.data
+         FROM listings JOIN listed_buildings USING (name, address)
+         LEFT JOIN details ON details.id = listings.id
+         WHERE last_seen = ?1 OR last_seen = ?2
+         ORDER BY last_seen DESC, position",
+    )?;
+    let rows = query
+        .query_map((last, previous), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut buildings: Vec<Building> = vec![];
+    for (building, apartment, last_seen, details) in rows {
+        let building: Building = serde_json::from_str(&building)?;
+        let mut apartment: Apartment = serde_json::from_str(&apartment)?;
+        if let Some(details) = details {
+            apartment.details = Some(serde_json::from_str(&details)?);
+        }
+        if last_seen != last {
+            apartment.status = ListingStatus::Gone;
+        }
+        match buildings
+            .iter_mut()
+            .find(|other| other.name == building.name && other.address == building.address)
+        {
+            // every listing of a unit has a row
+            Some(other)
+                if other
+                    .apartments
+                    .iter()
+                    .any(|other| other.id == apartment.id) => {}
+            Some(other) => other.apartments.push(apartment),
+            None => buildings.push(Building {
+                apartments: vec![apartment],
+                ..building
+            }),
+        }
+    }
+    Ok(buildings)
+}
+
+/// Stores the result of a search, marking its apartments as seen now.
+///
+/// Returns the buildings annotated with the changes since the previous search, including
+/// the apartments that are gone. The apartments of the `incomplete` searches that are missing
+/// may be on their failed pages, so they are kept as unchanged rather than gone.
+#[server]
+pub async fn save_listings(
+    buildings: Vec<Building>,
+    incomplete: Vec<String>,
+) -> Result<Vec<Building>> {
+    let now = chrono::Utc::now().timestamp();
+    Ok(DB.with(|db| store_listings(db, &buildings, &incomplete, now))?)
+}
+
+/// Stores the result of the search at `now`, as [`save_listings`] does.
+#[cfg(feature = "server")]
+fn store_listings(
+    db: &rusqlite::Connection,
+    buildings: &[Building],
+    incomplete: &[String],
+    now: i64,
+) -> std::result::Result<Vec<Building>, crate::Error> {
+    let transaction = db.unchecked_transaction()?;
+    let previous: Option<i64> =
+        transaction.query_row("SELECT MAX(time) FROM searches", [], |row| row.get(0))?;
+
+    let mut position = 0;
+    for building in buildings {
+        let data = serde_json::to_string(&Building {
+            apartments: vec![],
+            times: Default::default(),
+            ..building.clone()
+        })?;
+        transaction.execute(
+            "INSERT INTO listed_buildings VALUES (?1, ?2, ?3)
+             ON CONFLICT DO UPDATE SET data = ?3",
+            (&building.name, &building.address, data),
+        )?;
+        for apartment in &building.apartments {
+            // a unit shares its history with all its listings, current and past
+            let mut ids = apartment.ids();
+            let mut seen = vec![];
+            let mut k = 0;
+            while k < ids.len() {
+                let row = transaction
+                    .query_row(
+                        "SELECT data, last_seen FROM listings WHERE id = ?1",
+                        [ids[k] as i64],
+                        |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)),
+                    )
+                    .optional()?;
+                if let Some((data, last_seen)) = row {
+                    let other: Apartment = serde_json::from_str(&data)?;
+                    for id in other.ids() {
+                        if !ids.contains(&id) {
+                            ids.push(id);
+                        }
+                    }
+                    seen.push((other, last_seen));
+                }
+                k += 1;
+            }
+
+            let status = match seen
+                .iter()
+                .find(|(_, last_seen)| Some(*last_seen) == previous)
+            {
+                Some((seen, _)) => {
+                    let rent = apartment.rent.yen as i64 - seen.rent.yen as i64;
+                    let fees = apartment.fees.as_ref().map_or(0, |fees| fees.yen) as i64
+                        - seen.fees.as_ref().map_or(0, |fees| fees.yen) as i64;
+                    if rent != 0 || fees != 0 {
+                        ListingStatus::Changed { rent, fees }
+                    } else {
+                        ListingStatus::Unchanged
+                    }
+                }
+                // never seen, or relisted after missing from the previous search
+                None => ListingStatus::New,
+            };
+            let data = serde_json::to_string(&Apartment {
+                status,
+                ..apartment.clone()
+            })?;
+            for id in ids {
+                transaction.execute(
+                    "INSERT INTO listings VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)
+                     ON CONFLICT DO UPDATE
+                     SET name = ?2, address = ?3, data = ?4, position = ?5, last_seen = ?6",
+                    (
+                        id as i64,
+                        &building.name,
+                        &building.address,
+                        &data,
+                        position,
+                        now,
+                    ),
+                )?;
+            }
+            position += 1;
+        }
+    }
+
+    let mut query = transaction
+        .prepare("SELECT id, data FROM listings WHERE last_seen = ?1 ORDER BY position")?;
+    let missing = query
+        .query_map([previous], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    drop(query);
+    for (id, data) in missing {
+        let apartment: Apartment = serde_json::from_str(&data)?;
+        if !apartment
+            .searches
+            .iter()
+            .any(|search| incomplete.contains(search))
+        {
+            continue;
+        }
+        let data = serde_json::to_string(&Apartment {
+            status: ListingStatus::Unchanged,
+            ..apartment
+        })?;
+        transaction.execute(
+            "UPDATE listings SET data = ?2, position = ?3, last_seen = ?4 WHERE id = ?1",
+            (id, data, position, now),
+        )?;
+        position += 1;
+    }
+
+    transaction.execute(
+        "INSERT INTO searches VALUES (?1) ON CONFLICT DO NOTHING",
+        [now],
+    )?;
+
+    let buildings = load_listings(&transaction, now, previous)?;
+    transaction.commit()?;
+    Ok(buildings)
+}
+
+/// Buildings and apartments found by the last search, and the ones gone since the one before.
+#[server]
+pub async fn get_listings() -> Result<Vec<Building>> {
+    Ok(DB.with(|db| {
+        let mut query = db.prepare("SELECT time FROM searches ORDER BY time DESC LIMIT 2")?;
+        let times = query
+            .query_map([], |row| row.get::<_, i64>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        match times[..] {
+            [] => Ok(vec![]),
+            [last] => load_listings(db, last, None),
+            [last, previous, ..] => load_listings(db, last, Some(previous)),
+        }
+    })?)
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use crate::sources::{ListingSource, Suumo};
+
+    /// One apartment of each of the first `count` buildings of the saved page.
+    fn buildings(count: usize) -> Vec<Building> {
+        let doc = scraper::Html::parse_document(include_str!("../suumo.html"));
+        let (buildings, _) = Suumo.parse_page(&doc, 1).unwrap();
+        buildings
+            .into_iter()
+            .take(count)
+            .map(|mut building| {
+                building.apartments.truncate(1);
+                building
+            })
+            .collect()
+    }
+
+    fn statuses(buildings: &[Building]) -> Vec<(u64, ListingStatus)> {
+        buildings
+            .iter()
+            .flat_map(|building| &building.apartments)
+            .map(|apartment| (apartment.id, apartment.status))
+            .collect()
+    }
+
+    #[test]
+    fn diffs_listings() {
+        let db = rusqlite::Connection::open_in_memory().unwrap();
+        init_database(&db).unwrap();
+
+        // the fourth apartment is only found by search b, which fails the second time, and the
+        // fifth is only listed the second time
+        let mut first = buildings(5);
+        for (k, building) in first.iter_mut().enumerate() {
+            building.apartments[0].searches = vec![if k == 3 { "b" } else { "a" }.to_string()];
+        }
+        let added = first.pop().unwrap();
+        let ids: Vec<u64> = first
+            .iter()
+            .chain([&added])
+            .map(|building| building.apartments[0].id)
+            .collect();
+        let stored = store_listings(&db, &first, &[], 1).unwrap();
+        assert!(
+            statuses(&stored)
+                .iter()
+                .all(|(_, status)| *status == ListingStatus::New)
+        );
+
+        // unchanged, repriced, gone, missing from an incomplete search, and new
+        let mut second = vec![first[0].clone(), first[1].clone(), added];
+        second[1].apartments[0].rent.yen += 1_000;
+        let stored = store_listings(&db, &second, &["b".to_string()], 2).unwrap();
+        let mut statuses = statuses(&stored);
+        statuses.sort_by_key(|(id, _)| ids.iter().position(|other| other == id));
+        assert_eq!(
+            statuses,
+            [
+                (ids[0], ListingStatus::Unchanged),
+                (
+                    ids[1],
+                    ListingStatus::Changed {
+                        rent: 1_000,
+                        fees: 0
+                    }
+                ),
+                (ids[2], ListingStatus::Gone),
+                (ids[3], ListingStatus::Unchanged),
+                (ids[4], ListingStatus::New),
+            ]
+        );
+    }
+}
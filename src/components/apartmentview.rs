@@ -1,11 +1,74 @@
 use dioxus::prelude::*;
 
-use crate::Apartment;
+use crate::{Apartment, ApartmentDetails, Lease, ListingStatus, format_yen};
+
+#[component]
+pub fn StatusBadge(status: ListingStatus) -> Element {
+    match status {
+        ListingStatus::Unchanged => rsx! {},
+        ListingStatus::New => rsx! { span { class: "badge new", "NEW" } },
+        ListingStatus::Gone => rsx! { span { class: "badge gone", "gone" } },
+        ListingStatus::Changed { rent, fees } => {
+            let change = rent + fees;
+            let (class, arrow) = if change < 0 {
+                ("down", "↓")
+            } else {
+                ("up", "↑")
+            };
+            rsx! {
+                span { class: "badge {class}",
+                       title: "Rent {rent:+} yen, fees {fees:+} yen",
+                       "{arrow} {format_yen(change.unsigned_abs())}"
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn DetailsView(details: ApartmentDetails) -> Element {
+    rsx! {
+        div { class: "details",
+              if let Some(orientation) = &details.orientation {
+                  span { class: "orientation",
+                         i { class: "fa-solid fa-compass" }
+                         "{orientation}"
+                  }
+              }
+              if let Some(move_in) = &details.move_in {
+                  span { class: "move-in",
+                         i { class: "fa-solid fa-truck-moving" }
+                         "{move_in}"
+                  }
+              }
+              match details.lease {
+                  Some(Lease::Regular) => rsx! { span { class: "lease", "普通借家" } },
+                  Some(Lease::FixedTerm) => rsx! { span { class: "lease fixed-term", "定期借家" } },
+                  None => rsx! {},
+              }
+        }
+        if !details.facilities.is_empty() {
+            div { class: "facilities",
+                  {details.facilities.join("、")}
+            }
+        }
+        if !details.photos.is_empty() {
+            div { class: "photos",
+                  for photo in details.photos.iter().take(4) {
+                      img { src: "{photo}" }
+                  }
+                  if details.photos.len() > 4 {
+                      span { "+{details.photos.len() - 4}" }
+                  }
+            }
+        }
+    }
+}
 
 #[component]
 pub fn ApartmentView(name: String, apartment: Apartment) -> Element {
     rsx!(
-        li { class: "apartment",
+        li { class: if apartment.status == ListingStatus::Gone { "apartment gone" } else { "apartment" },
              onmouseenter:
                  move |_| {
                      let name = name.clone();
@@ -18,6 +81,7 @@ pub fn ApartmentView(name: String, apartment: Apartment) -> Element {
                      let _ = document::eval(r#"unfocusMarker();"#).await;
                  }
              },
+             StatusBadge { status: apartment.status }
              img {
                  src: "{apartment.plan}"
              }
@@ -27,36 +91,82 @@ pub fn ApartmentView(name: String, apartment: Apartment) -> Element {
                  div {
                      div { class: "layout",
                            span { class: "area",
-                                  "{apartment.area}"
+                                  "{apartment.area}m²"
                            }
                            span { class: "kind",
-                                  "{apartment.kind}"
+                                  "{apartment.layout}"
+                           }
+                           if let Some(floor) = apartment.floor {
+                               span { class: "floor",
+                                      "{floor}"
+                               }
                            }
                      }
                      div { class: "rent-fees",
                            span { class: "rent",
                                   i { class: "fa-solid fa-house" }
-                                  "{apartment.rent}"
+                                  "{apartment.rent.text}"
                            }
-                           if apartment.fees.is_some() {
+                           if let Some(fees) = &apartment.fees {
                                span { class: "fees",
                                       i { class: "fa-solid fa-list-check" }
-                                      "{apartment.fees.as_deref().unwrap()}"
+                                      "{fees.text}"
                                }
                            }
                      }
                      div { class: "initial-fees",
-                           if apartment.deposit.is_some() {
+                           if let Some(deposit) = &apartment.deposit {
                            span { class: "deposit",
                                   i { class: "fa-solid fa-money-bill-transfer" }
-                                  "{apartment.deposit.as_deref().unwrap()}"
+                                  "{deposit.text}"
                            }
                            }
-                           if apartment.key_money.is_some() {
+                           if let Some(key_money) = &apartment.key_money {
                            span { class: "key_money",
                                   i { class: "fa-solid fa-key" }
-                                  "{apartment.key_money.as_deref().unwrap()}"
+                                  "{key_money.text}"
+                           }
+                           }
+                     }
+                     if let Some(details) = apartment.details.clone() {
+                         DetailsView { details }
+                     }
+                     if !apartment.alternatives.is_empty() {
+                         div { class: "alternatives",
+                               i { class: "fa-solid fa-link" }
+                               "Also listed by "
+                               for (k, alternative) in apartment.alternatives.iter().enumerate() {
+                                   a {
+                                       href: "{alternative.url}",
+                                       target: "_blank",
+                                       onclick: move |event| event.stop_propagation(),
+                                       " #{k + 2}"
+                                   }
+                               }
+                         }
+                     }
+                     if !apartment.searches.is_empty() {
+                         div { class: "searches",
+                               i { class: "fa-solid fa-magnifying-glass" }
+                               " "
+                               {apartment.searches.join(", ")}
+                         }
+                     }
+                     div { class: "totals",
+                           span { class: "monthly",
+                                  title: "Rent and fees",
+                                  i { class: "fa-solid fa-calendar-days" }
+                                  {format_yen(apartment.monthly())}
+                           }
+                           span { class: "rent-per-area",
+                                  title: "Rent per square meter",
+                                  i { class: "fa-solid fa-ruler-combined" }
+                                  "{format_yen(apartment.rent_per_area())}/m²"
                            }
+                           span { class: "initial",
+                                  title: "Deposit, key money and first month",
+                                  i { class: "fa-solid fa-coins" }
+                                  {format_yen(apartment.initial())}
                            }
                      }
                  }
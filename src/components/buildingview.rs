@@ -1,6 +1,9 @@
 use dioxus::prelude::*;
 
-use crate::{Building, TransportationMode, components::ApartmentView};
+use crate::{
+    Building, ListingStatus, TransportationMode,
+    components::{ApartmentView, StatusBadge},
+};
 
 #[component]
 pub fn BuildingView(building: Building) -> Element {
@@ -9,9 +12,14 @@ pub fn BuildingView(building: Building) -> Element {
     rsx! {
         li { class: "building",
              div { class: "building-head",
-                   h3 { "{building.name}" }
+                   div { class: "building-title",
+                         h3 { "{building.name}" }
+                         if let Some(status @ (ListingStatus::New | ListingStatus::Gone)) = building.status() {
+                             StatusBadge { status }
+                         }
+                   }
                    h4 {
-                       for (_, (criterion, time)) in times {
+                       for (k, (criterion, time)) in times {
                            span { class: "time-indicator",
                                   "style": "color: {criterion.color}",
                                   match criterion.mode {
@@ -24,11 +32,70 @@ pub fn BuildingView(building: Building) -> Element {
                                       TransportationMode::Public =>
                                           rsx! { i { class: "fa-solid fa-train-subway" } },
                                   }
-                                  "{(*time as f32 / 60.0) as usize}"
+                                  if let Some((min, max)) = building.time_ranges.get(k) {
+                                      "{min / 60}–{max / 60}"
+                                  } else {
+                                      "{(*time as f32 / 60.0) as usize}"
+                                  }
                            }
                        }
                    }
              }
+             div { class: "building-info",
+                   if let Some(built) = building.built {
+                       span { class: "built",
+                              i { class: "fa-solid fa-calendar" }
+                              "{built}"
+                       }
+                   }
+                   if let Some(floors) = building.floors {
+                       span { class: "floors",
+                              i { class: "fa-solid fa-building" }
+                              "{floors}F"
+                       }
+                   }
+                   if let Some(precision) = building.precision.filter(|precision| precision.radius() > 0.0) {
+                       span { class: "imprecise",
+                              title: "Located at {precision.label()} level, so possibly this far off",
+                              i { class: "fa-solid fa-location-crosshairs" }
+                              "±{precision.radius()}m"
+                       }
+                   }
+                   if building.coordinates.is_none() {
+                       span { class: "unlocated",
+                              title: "The address could not be geocoded, so travel times are unknown",
+                              i { class: "fa-solid fa-triangle-exclamation" }
+                              "No location"
+                       }
+                   }
+                   if building.old_seismic_code() {
+                       span { class: "old-seismic-code",
+                              title: "May predate the June 1981 seismic code",
+                              i { class: "fa-solid fa-triangle-exclamation" }
+                              "旧耐震"
+                       }
+                   }
+             }
+             ul { class: "stations",
+                  for access in building.stations {
+                      li {
+                          i { class: "fa-solid fa-train-subway" }
+                          "{access.line} {access.station}"
+                          if let Some(bus) = access.bus {
+                              span { class: "bus",
+                                     i { class: "fa-solid fa-bus" }
+                                     "{bus}"
+                              }
+                          }
+                          if let Some(walk) = access.walk {
+                              span { class: "walk",
+                                     i { class: "fa-solid fa-person-walking" }
+                                     "{walk}"
+                              }
+                          }
+                      }
+                  }
+             }
              ul { class: "apartments",
                   for apartment in building.apartments {
                       ApartmentView { name: building.name.clone(), apartment: apartment }
@@ -1,9 +1,15 @@
+use std::time::Duration;
+
 use dioxus::prelude::*;
+use dioxus_logger::tracing;
+use futures::{StreamExt, channel::mpsc};
 
 use crate::{
-    Building, Criterion, Error, SUUMOURL, TransportationMode, backend,
-    geocode::{self, ClonableRequestBuilder},
+    Building, Criterion, Error, SUUMOURL, SavedSearch, ScrapeReport, TransportationMode, backend,
+    deduplicate,
+    geocode::{self, GeocoderSettings, Provider},
     get_string, random_color, scrape,
+    search::{LAYOUTS, SearchQuery, WARDS},
 };
 
 #[component]
@@ -77,26 +83,341 @@ fn Criteria(criteria_raw: Signal<Vec<Criterion>>) -> Element {
     }
 }
 
+#[component]
+fn SavedSearches(searches: Signal<Vec<SavedSearch>>, selected: Signal<usize>) -> Element {
+    rsx! {
+        div { id: "saved_searches",
+              span {  }
+              span {  }
+              label { "Name" }
+              label { "Search URL" }
+              span {  }
+              for (k, search) in searches().into_iter().enumerate() {
+                  if searches().len() > 1 {
+                      button {
+                          r#type: "button",
+                          onclick: move |_| {
+                              searches.remove(k);
+                              selected.set(0);
+                          },
+                          i { class: "fa-solid fa-circle-minus fa-lg"}
+                      }
+                  } else {
+                      span {  }
+                  }
+                  input {
+                      r#type: "checkbox",
+                      name: "search_enabled{k}",
+                      title: "Enabled",
+                      checked: search.enabled,
+                      onchange: move |event| searches.write()[k].enabled = event.checked()
+                  }
+                  input {
+                      r#type: "text",
+                      name: "search_name{k}",
+                      value: "{search.name}",
+                      oninput: move |event| searches.write()[k].name = event.value()
+                  }
+                  input {
+                      r#type: "text",
+                      name: "suumo_url{k}",
+                      value: "{search.url}",
+                      oninput: move |event| searches.write()[k].url = event.value()
+                  }
+                  input {
+                      r#type: "radio",
+                      name: "selected_search",
+                      title: "Edit below",
+                      checked: selected() == k,
+                      onchange: move |_| selected.set(k)
+                  }
+              }
+        }
+        button {
+            id: "add_search",
+            r#type: "button",
+            onclick: move |_| {
+                let last = searches().last().cloned();
+                searches.push(SavedSearch {
+                    name: format!("Search {}", searches().len() + 1),
+                    url: last.map_or(SUUMOURL.to_string(), |last| last.url),
+                    enabled: true,
+                });
+                selected.set(searches().len() - 1);
+            },
+            i { class: "fa-solid fa-circle-plus fa-lg"}
+        }
+    }
+}
+
+#[component]
+fn SearchQueryForm(searches: Signal<Vec<SavedSearch>>, selected: usize) -> Element {
+    let Some(search) = searches().get(selected).cloned() else {
+        return rsx! {};
+    };
+    let query = SearchQuery::parse(&search.url);
+    let wards = query.wards();
+    let layouts = query.layouts();
+    let (rent_min, rent_max) = query.rent();
+    let (area_min, area_max) = query.area();
+    let options = query.options().join(", ");
+
+    let mut update = move |edit: &dyn Fn(&mut SearchQuery)| {
+        let mut query = SearchQuery::parse(&searches.read()[selected].url);
+        edit(&mut query);
+        searches.write()[selected].url = query.to_string();
+    };
+    let bound = |value: String| value.parse::<f64>().ok();
+
+    rsx! {
+        details { id: "search_query",
+                  summary { "Search: {search.name}" }
+                  fieldset {
+                      legend { "Wards" }
+                      for (code, ward) in WARDS {
+                          label {
+                              input {
+                                  r#type: "checkbox",
+                                  name: "ward{code}",
+                                  checked: wards.contains(&code),
+                                  onchange: move |event| {
+                                      update(&|query| query.set_ward(code, event.checked()))
+                                  }
+                              }
+                              "{ward}"
+                          }
+                      }
+                  }
+                  fieldset {
+                      legend { "Layouts" }
+                      for (code, layout) in LAYOUTS {
+                          label {
+                              input {
+                                  r#type: "checkbox",
+                                  name: "layout{code}",
+                                  checked: layouts.contains(&code),
+                                  onchange: move |event| {
+                                      update(&|query| query.set_layout(code, event.checked()))
+                                  }
+                              }
+                              "{layout}"
+                          }
+                      }
+                  }
+                  div { class: "ranges",
+                        label { for: "rent_min", "Rent (万円)" }
+                        input {
+                            class: "time",
+                            name: "rent_min",
+                            r#type: "number",
+                            step: "0.5",
+                            min: "0",
+                            value: rent_min.map(|rent| rent.to_string()).unwrap_or_default(),
+                            onchange: move |event| {
+                                update(&|query| query.set_rent(bound(event.value()), rent_max))
+                            }
+                        }
+                        "–"
+                        input {
+                            class: "time",
+                            name: "rent_max",
+                            r#type: "number",
+                            step: "0.5",
+                            min: "0",
+                            value: rent_max.map(|rent| rent.to_string()).unwrap_or_default(),
+                            onchange: move |event| {
+                                update(&|query| query.set_rent(rent_min, bound(event.value())))
+                            }
+                        }
+                        label { for: "area_min", "Area (m²)" }
+                        input {
+                            class: "time",
+                            name: "area_min",
+                            r#type: "number",
+                            min: "0",
+                            value: area_min.map(|area| area.to_string()).unwrap_or_default(),
+                            onchange: move |event| {
+                                update(&|query| query.set_area(bound(event.value()), area_max))
+                            }
+                        }
+                        "–"
+                        input {
+                            class: "time",
+                            name: "area_max",
+                            r#type: "number",
+                            min: "0",
+                            value: area_max.map(|area| area.to_string()).unwrap_or_default(),
+                            onchange: move |event| {
+                                update(&|query| query.set_area(area_min, bound(event.value())))
+                            }
+                        }
+                  }
+                  if !options.is_empty() {
+                      div { class: "options", "Other options (tc): {options}" }
+                  }
+        }
+    }
+}
+
+#[component]
+fn CrawlingForm(config: Signal<scrape::ScrapeConfig>) -> Element {
+    let current = config();
+    rsx! {
+        details { id: "crawling",
+                  summary { "Crawling" }
+                  label { for: "concurrency", "Parallel requests" }
+                  input {
+                      class: "time",
+                      name: "concurrency",
+                      r#type: "number",
+                      min: "1",
+                      max: "16",
+                      value: current.concurrency,
+                      onchange: move |event| {
+                          if let Ok(concurrency) = event.value().parse() {
+                              config.write().concurrency = concurrency;
+                          }
+                      }
+                  }
+                  label { for: "delay", "Delay between requests (s)" }
+                  input {
+                      class: "time",
+                      name: "delay",
+                      r#type: "number",
+                      min: "0",
+                      step: "0.1",
+                      value: current.delay.as_secs_f64(),
+                      onchange: move |event| {
+                          if let Ok(delay) = event.value().parse::<f64>() {
+                              config.write().delay = Duration::from_secs_f64(delay.max(0.0));
+                          }
+                      }
+                  }
+                  label { for: "retries", "Retries" }
+                  input {
+                      class: "time",
+                      name: "retries",
+                      r#type: "number",
+                      min: "0",
+                      max: "10",
+                      value: current.retries,
+                      onchange: move |event| {
+                          if let Ok(retries) = event.value().parse() {
+                              config.write().retries = retries;
+                          }
+                      }
+                  }
+                  label { for: "max_pages", "Max pages per search" }
+                  input {
+                      class: "time",
+                      name: "max_pages",
+                      r#type: "number",
+                      min: "1",
+                      value: current.max_pages,
+                      onchange: move |event| {
+                          if let Ok(max_pages) = event.value().parse() {
+                              config.write().max_pages = max_pages;
+                          }
+                      }
+                  }
+        }
+    }
+}
+
+#[component]
+fn GeocodingForm(settings: Signal<GeocoderSettings>) -> Element {
+    let current = settings();
+    let save = move || async move {
+        if let Err(error) = backend::set_geocoder_settings(settings()).await {
+            tracing::error!("geocoder settings not saved: {error}");
+        }
+    };
+    rsx! {
+        details { id: "geocoding",
+                  summary { "Geocoding" }
+                  label { for: "provider", "Provider" }
+                  select {
+                      name: "provider",
+                      onchange: move |event| async move {
+                          let provider = Provider::ALL
+                              .into_iter()
+                              .find(|provider| format!("{provider:?}") == event.value());
+                          if let Some(provider) = provider {
+                              settings.write().provider = provider;
+                              save().await;
+                          }
+                      },
+                      for provider in Provider::ALL {
+                          option {
+                              value: "{provider:?}",
+                              selected: provider == current.provider,
+                              "{provider.label()}"
+                          }
+                      }
+                  }
+                  if current.provider == Provider::Nominatim {
+                      label { for: "endpoint", "Nominatim URL" }
+                      input {
+                          r#type: "text",
+                          name: "endpoint",
+                          value: "{current.endpoint}",
+                          onchange: move |event| async move {
+                              settings.write().endpoint = event.value().trim().to_string();
+                              save().await;
+                          }
+                      }
+                  }
+                  if current.provider == Provider::Offline {
+                      p { class: "hint",
+                          "Addresses are looked up in the 位置参照情報 CSVs loaded with "
+                          code { "suumo-traveltime import-addresses <file.csv>..." }
+                      }
+                  }
+        }
+    }
+}
+
 #[component]
 pub fn CriteriaForm(
     app_id: String,
     api_key: String,
-    geocode_request: ClonableRequestBuilder,
     criteria_raw: Signal<Vec<Criterion>>,
     criteria_located: Signal<Vec<Criterion>>,
     buildings: Signal<Vec<Building>>,
     scrape_progress: Signal<f64>,
+    scrape_report: Signal<ScrapeReport>,
 ) -> Element {
-    let suumo_url = use_server_future(backend::get_suumo_url)?;
-    let mut suumo_url_sig = use_signal(|| SUUMOURL.to_string());
+    let saved_searches = use_server_future(backend::get_saved_searches)?;
+    let mut searches: Signal<Vec<SavedSearch>> = use_signal(Vec::new);
+    let selected: Signal<usize> = use_signal(|| 0);
+    use_effect(move || {
+        if let Some(Ok(saved)) = saved_searches() {
+            searches.set(saved);
+        }
+    });
+    let mut fetch_details = use_signal(|| false);
+    let mut time_ranges = use_signal(|| false);
+    let scrape_config = use_signal(scrape::ScrapeConfig::default);
+    let mut running: Signal<Option<dioxus::core::Task>> = use_signal(|| None);
+    let mut resume = use_signal(|| false);
+    let mut interrupted = use_resource(backend::has_scraped_pages);
+    let mut geocoder_settings = use_signal(GeocoderSettings::default);
+    let _geocoder_settings: Resource<Result<(), Error>> = use_resource(move || async move {
+        geocoder_settings.set(backend::get_geocoder_settings().await?);
+        Ok(())
+    });
 
     let submit = move |event: FormEvent| {
         event.prevent_default();
+        // a new search replaces the running one
+        if let Some(task) = running.take() {
+            task.cancel();
+        }
         // TODO: ErrorBoundary to handle errors
         let api_key = api_key.clone();
         let app_id = app_id.clone();
-        let request = geocode_request.clone().0;
-        async move {
+        let geocoder = geocoder_settings().geocoder(&app_id, &api_key);
+        let search = async move {
             // get all search criteria
             let mut criteria = vec![];
             for (k, criterion) in criteria_raw().into_iter().enumerate() {
@@ -122,48 +443,106 @@ pub fn CriteriaForm(
             }
 
             backend::set_criteria(criteria.clone()).await?;
-            backend::set_suumo_url(suumo_url_sig()).await?;
+            backend::set_saved_searches(searches()).await?;
 
             criteria_raw.set(criteria.clone());
 
             // geocode the criteria
             let mut criteria_loc = vec![];
             for criterion in criteria {
-                let request = request.try_clone().unwrap();
-                let location = geocode::geocode(&criterion.address, request).await?;
+                let location = geocode::geocode(&criterion.address, geocoder.as_ref()).await?;
                 criteria_loc.push(Criterion {
-                    location,
+                    location: location.coordinates,
                     ..criterion
                 });
             }
             criteria_located.set(criteria_loc.clone());
 
-            // scrape SUUMO
-            let mut buildings_v = scrape::scrape(scrape_progress, request).await?;
-            geocode::get_travel_time(&app_id, &api_key, &mut buildings_v, &criteria_loc).await?;
-            buildings.set(buildings_v);
+            // scrape the portals, and route each page as soon as it is geocoded
+            let config = scrape::ScrapeConfig {
+                resume: resume(),
+                ..scrape_config()
+            };
+            if !config.resume {
+                backend::clear_scraped_pages().await?;
+            }
+            let searches = searches();
+            buildings.set(vec![]);
+            let (pages_sender, mut pages) = mpsc::unbounded();
+            let scraping = scrape::scrape_searches(
+                &searches,
+                &config,
+                scrape_progress,
+                geocoder.as_ref(),
+                pages_sender,
+            );
+            let routing = async {
+                while let Some(mut page) = pages.next().await {
+                    geocode::get_travel_time(
+                        &app_id,
+                        &api_key,
+                        &mut page,
+                        &criteria_loc,
+                        time_ranges(),
+                    )
+                    .await?;
+                    let mut listed = buildings();
+                    listed.extend(page);
+                    buildings.set(deduplicate(listed));
+                }
+                Ok::<_, Error>(())
+            };
+            let (scraped, routed) = futures::join!(scraping, routing);
+            let (buildings_v, report) = scraped?;
+            routed?;
+            let incomplete = report.incomplete.clone();
+            scrape_report.set(report);
 
-            Ok(())
-        }
+            // the saved listings add the gone apartments, whose times are cached if known
+            let mut buildings_v = backend::save_listings(buildings_v, incomplete).await?;
+            backend::clear_scraped_pages().await?;
+            geocode::get_travel_time(
+                &app_id,
+                &api_key,
+                &mut buildings_v,
+                &criteria_loc,
+                time_ranges(),
+            )
+            .await?;
+            buildings.set(buildings_v.clone());
+
+            // only the buildings within reach are listed, so only those need details
+            if fetch_details() {
+                let mut reachable = buildings_v
+                    .into_iter()
+                    .filter(|building| building.within_reach(&criteria_loc))
+                    .collect::<Vec<_>>();
+                let problems =
+                    scrape::scrape_details(&mut reachable, &config, scrape_progress).await;
+                scrape_report.write().problems.extend(problems);
+                buildings.set(reachable);
+            }
+
+            Ok::<_, Error>(())
+        };
+        running.set(Some(spawn(async move {
+            if let Err(error) = search.await {
+                tracing::error!("search failed: {error}");
+            }
+            running.set(None);
+            interrupted.restart();
+        })));
     };
 
-    match suumo_url() {
+    match saved_searches() {
         None => rsx! { div { "Checking database..." } },
-        Some(Ok(_suumo_url)) => {
+        Some(Ok(_)) => {
             // tracing::debug!("{suumo_url}");
             rsx! {
                 form { id: "criteria_form",
                        onsubmit: submit,
-                       div { id: "suumo",
-                             label { for: "suumo_url", "Suumo URL" }
-                             input {
-                                 r#type: "url",
-                                 name: "suumo_url",
-                                 key: "suumo_url",
-                                 value: "{suumo_url_sig}",
-                                 oninput: move |event| suumo_url_sig.set(event.value())
-                             }
-                       }
+                       SavedSearches { searches, selected }
+                       SearchQueryForm { searches, selected: selected() }
                        Criteria { criteria_raw }
                        button {
                            id: "add_criterion",
@@ -174,12 +553,57 @@ pub fn CriteriaForm(
                            },
                            i { class: "fa-solid fa-circle-plus fa-lg"}
                        }
+                       CrawlingForm { config: scrape_config }
+                       GeocodingForm { settings: geocoder_settings }
+                       label { id: "fetch_details",
+                               input {
+                                   r#type: "checkbox",
+                                   name: "fetch_details",
+                                   checked: fetch_details(),
+                                   onchange: move |event| fetch_details.set(event.checked())
+                               }
+                               "Fetch details"
+                       }
+                       label { id: "time_ranges",
+                               title: "Also route to the edge of the area where imprecisely located buildings may be",
+                               input {
+                                   r#type: "checkbox",
+                                   name: "time_ranges",
+                                   checked: time_ranges(),
+                                   onchange: move |event| time_ranges.set(event.checked())
+                               }
+                               "Time ranges"
+                       }
                        button {
                            id: "submit_search",
                            r#type: "submit",
+                           onclick: move |_| resume.set(false),
                            i { class: "fa-solid fa-magnifying-glass fa-lg"}
                            " Search"
                        }
+                       if running().is_some() {
+                           button {
+                               id: "cancel_search",
+                               r#type: "button",
+                               onclick: move |_| {
+                                   if let Some(task) = running.take() {
+                                       task.cancel();
+                                   }
+                                   interrupted.restart();
+                               },
+                               i { class: "fa-solid fa-xmark fa-lg"}
+                               " Cancel"
+                           }
+                       } else if matches!(interrupted(), Some(Ok(true))) {
+                           button {
+                               id: "resume_search",
+                               r#type: "submit",
+                               title: "Continue the interrupted search from its last scraped pages",
+                               onclick: move |_| resume.set(true),
+                               i { class: "fa-solid fa-forward fa-lg"}
+                               " Resume"
+                           }
+                       }
                 }
 
             }
@@ -0,0 +1,150 @@
+use dioxus::prelude::*;
+
+use crate::{FACILITIES, Filters, Layout};
+
+const LAYOUTS: [&str; 10] = [
+    "1K", "1DK", "1LDK", "2K", "2DK", "2LDK", "3K", "3DK", "3LDK", "4LDK",
+];
+
+#[component]
+pub fn FiltersForm(filters: Signal<Filters>) -> Element {
+    rsx! {
+        div { id: "filters",
+              label { for: "max_station_walk", "Walk to station" }
+              input {
+                  class: "time",
+                  name: "max_station_walk",
+                  r#type: "number",
+                  min: "1",
+                  max: "99",
+                  oninput: move |event| {
+                      filters.write().max_station_walk = event.value().parse().ok();
+                  }
+              }
+              label { for: "station", "Station or line" }
+              input {
+                  r#type: "search",
+                  name: "station",
+                  oninput: move |event| {
+                      filters.write().station = event.value().trim().to_string();
+                  }
+              }
+              label { for: "max_age", "Max age" }
+              input {
+                  class: "time",
+                  name: "max_age",
+                  r#type: "number",
+                  min: "0",
+                  max: "99",
+                  oninput: move |event| {
+                      filters.write().max_age = event.value().parse().ok();
+                  }
+              }
+              label { for: "min_floors", "Min floors" }
+              input {
+                  class: "time",
+                  name: "min_floors",
+                  r#type: "number",
+                  min: "1",
+                  max: "99",
+                  oninput: move |event| {
+                      filters.write().min_floors = event.value().parse().ok();
+                  }
+              }
+              label { for: "new_seismic_code", "Post-1981 seismic code" }
+              input {
+                  name: "new_seismic_code",
+                  r#type: "checkbox",
+                  onchange: move |event| {
+                      filters.write().new_seismic_code = event.checked();
+                  }
+              }
+              label { for: "max_monthly", "Max rent and fees (万円)" }
+              input {
+                  class: "time",
+                  name: "max_monthly",
+                  r#type: "number",
+                  min: "0",
+                  step: "0.1",
+                  oninput: move |event| {
+                      filters.write().max_monthly = event
+                          .value()
+                          .parse::<f64>()
+                          .ok()
+                          .map(|man| (man * 10_000.0).round() as u64);
+                  }
+              }
+              label { for: "min_layout", "Min layout" }
+              select {
+                  name: "min_layout",
+                  oninput: move |event| {
+                      filters.write().min_layout = event.value().parse::<Layout>().ok();
+                  },
+                  option { value: "", "Any" }
+                  for layout in LAYOUTS {
+                      option { value: layout, "{layout}" }
+                  }
+              }
+              label { for: "min_area", "Min area (m²)" }
+              input {
+                  class: "time",
+                  name: "min_area",
+                  r#type: "number",
+                  min: "0",
+                  oninput: move |event| {
+                      filters.write().min_area = event.value().parse().ok();
+                  }
+              }
+              label { for: "no_ground_floor", "Not ground floor" }
+              input {
+                  name: "no_ground_floor",
+                  r#type: "checkbox",
+                  onchange: move |event| {
+                      filters.write().no_ground_floor = event.checked();
+                  }
+              }
+              label { for: "min_floor", "Min floor" }
+              input {
+                  class: "time",
+                  name: "min_floor",
+                  r#type: "number",
+                  min: "1",
+                  max: "99",
+                  oninput: move |event| {
+                      filters.write().min_floor = event.value().parse().ok();
+                  }
+              }
+              label { for: "orientation", "Facing" }
+              input {
+                  class: "time",
+                  r#type: "search",
+                  name: "orientation",
+                  oninput: move |event| {
+                      filters.write().orientation = event.value().trim().to_string();
+                  }
+              }
+              label { for: "regular_lease", "Renewable lease" }
+              input {
+                  name: "regular_lease",
+                  r#type: "checkbox",
+                  onchange: move |event| {
+                      filters.write().regular_lease = event.checked();
+                  }
+              }
+              for facility in FACILITIES {
+                  label { for: "facility_{facility}", "{facility}" }
+                  input {
+                      name: "facility_{facility}",
+                      r#type: "checkbox",
+                      onchange: move |event| {
+                          let facilities = &mut filters.write().facilities;
+                          facilities.retain(|other| other != facility);
+                          if event.checked() {
+                              facilities.push(facility.to_string());
+                          }
+                      }
+                  }
+              }
+        }
+    }
+}
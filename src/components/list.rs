@@ -3,15 +3,16 @@ use dioxus::prelude::*;
 use crate::Building;
 use crate::Criterion;
 use crate::Error;
+use crate::Filters;
+use crate::ScrapeReport;
 use crate::backend;
 use crate::components::BuildingView;
 use crate::components::CriteriaForm;
-use crate::geocode::geocode_request;
+use crate::components::FiltersForm;
+use crate::components::ReportView;
 
 #[component]
 pub fn List(app_id: String, api_key: String) -> Element {
-    let geocode_request = geocode_request(&app_id, &api_key);
-
     let mut criteria_raw: Signal<Vec<Criterion>> = use_signal(Vec::new);
     let criteria_located: Signal<Vec<Criterion>> = use_signal(Vec::new);
 
@@ -21,9 +22,20 @@ pub fn List(app_id: String, api_key: String) -> Element {
         Ok(())
     });
 
-    let buildings: Signal<Vec<Building>> = use_signal(Vec::new);
+    let mut buildings: Signal<Vec<Building>> = use_signal(Vec::new);
+
+    // show the last search until a new one starts
+    let _listings: Resource<Result<(), Error>> = use_resource(move || async move {
+        let listings = backend::get_listings().await?;
+        if buildings.peek().is_empty() {
+            buildings.set(listings);
+        }
+        Ok(())
+    });
+    let filters: Signal<Filters> = use_signal(Filters::default);
 
     let scrape_progress: Signal<f64> = use_signal(|| 0.0);
+    let scrape_report: Signal<ScrapeReport> = use_signal(ScrapeReport::default);
 
     let mut mounted_map: Signal<bool> = use_signal(|| false);
     let mut initialized_map = false;
@@ -50,15 +62,24 @@ pub fn List(app_id: String, api_key: String) -> Element {
             }
 
             let buildings = buildings();
+            let filters = filters();
             let buildings = buildings
                 .iter()
-                .filter(|building| building.times.len() == criteria.len());
+                .filter(|building| building.within_reach(&criteria))
+                .filter_map(|building| filters.apply(building));
             for building in buildings {
                 let name = building.name.clone();
-                let lat = building.coordinates.0;
-                let lng = building.coordinates.1;
+                let Some((lng, lat)) = building.coordinates else {
+                    continue;
+                };
+                // imprecise locations are circled by how far off they may be
+                let radius = building
+                    .precision
+                    .map_or(0.0, |precision| precision.radius());
                 spawn(async move {
-                    let _ = document::eval(&format!(r#"addMarker("{name}", {lat}, {lng});"#)).await;
+                    let _ =
+                        document::eval(&format!(r#"addMarker("{name}", {lng}, {lat}, {radius});"#))
+                            .await;
                 });
             }
 
@@ -74,15 +95,21 @@ pub fn List(app_id: String, api_key: String) -> Element {
                     CriteriaForm {
                         app_id,
                         api_key,
-                        geocode_request,
                         criteria_raw,
                         criteria_located,
                         buildings,
-                        scrape_progress
+                        scrape_progress,
+                        scrape_report
                     }
+                    FiltersForm { filters }
+                    ReportView { report: scrape_report() }
 
                     {
-                        let buildings = buildings().into_iter().filter(|building| building.times.len() == criteria_located().len());
+                        let filters = filters();
+                        let buildings = buildings()
+                            .into_iter()
+                            .filter(|building| building.within_reach(&criteria_located()))
+                            .filter_map(|building| filters.apply(&building));
                         let bui_count = buildings.clone().count();
                         let apt_count = buildings.clone().fold(0, |count, building| count + building.apartments.len());
                         let progress = (scrape_progress() * 100.0).round() as usize;
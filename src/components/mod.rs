@@ -1,9 +1,13 @@
 mod apartmentview;
 mod buildingview;
 mod criteria;
+mod filters;
 mod list;
+mod report;
 
 pub use apartmentview::*;
 pub use buildingview::*;
 pub use criteria::*;
+pub use filters::*;
 pub use list::*;
+pub use report::*;
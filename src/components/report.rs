@@ -0,0 +1,25 @@
+use dioxus::prelude::*;
+
+use crate::ScrapeReport;
+
+#[component]
+pub fn ReportView(report: ScrapeReport) -> Element {
+    let count = report.problems.len();
+    if count == 0 {
+        return rsx! {};
+    }
+
+    rsx! {
+        details { id: "report",
+                  summary {
+                      i { class: "fa-solid fa-triangle-exclamation" }
+                      if count == 1 { " 1 problem" } else { " {count} problems" }
+                  }
+                  ul {
+                      for problem in report.problems {
+                          li { "{problem}" }
+                      }
+                  }
+        }
+    }
+}
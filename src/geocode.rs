@@ -1,67 +1,480 @@
+use std::time::Duration;
+
 use chrono::{Datelike, Days, Local, NaiveTime, Weekday};
-use reqwest::{Client, RequestBuilder};
+use futures::{FutureExt, future::LocalBoxFuture, lock::Mutex};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Building, Criterion, Error, TransportationMode,
+    addresses::{self, normalize},
+    backend,
+};
+
+/// Why an address has no coordinates.
+#[derive(thiserror::Error, Clone, PartialEq, Debug)]
+pub enum GeocodeError {
+    #[error("{0} not found")]
+    NotFound(String),
+    #[error("quota exceeded")]
+    QuotaExceeded,
+    #[error("authentication failed")]
+    AuthFailure,
+    #[error("malformed response: {0}")]
+    MalformedResponse(String),
+    #[error("request rejected: {0}")]
+    Rejected(String),
+}
+
+/// The start of a response, enough to tell what went wrong.
+fn excerpt(text: &str) -> String {
+    text.chars().take(200).collect()
+}
 
-use crate::{Building, Criterion, Error, TransportationMode, backend};
+/// First of the results, which are missing or empty when nothing matches.
+fn first(results: &serde_json::Value) -> Option<&serde_json::Value> {
+    results.as_array()?.first()
+}
 
-pub struct ClonableRequestBuilder(pub RequestBuilder);
+/// How closely geocoded coordinates locate an address, from the coarsest.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub enum Precision {
+    Prefecture,
+    City,
+    Chome,
+    Block,
+    Exact,
+}
+
+impl Precision {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Precision::Prefecture => "prefecture",
+            Precision::City => "city",
+            Precision::Chome => "chome",
+            Precision::Block => "block",
+            Precision::Exact => "exact",
+        }
+    }
 
-impl Clone for ClonableRequestBuilder {
-    fn clone(&self) -> Self {
-        ClonableRequestBuilder(self.0.try_clone().unwrap())
+    /// Typical distance in meters between coordinates of this precision and the building.
+    pub fn radius(&self) -> f64 {
+        match self {
+            Precision::Prefecture => 20_000.0,
+            Precision::City => 2_000.0,
+            Precision::Chome => 250.0,
+            Precision::Block => 50.0,
+            Precision::Exact => 0.0,
+        }
     }
 }
 
-impl PartialEq for ClonableRequestBuilder {
-    fn eq(&self, _other: &Self) -> bool {
-        false
+/// Geocoded coordinates, as (longitude, latitude), and how close they are to the address.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Location {
+    pub coordinates: (f64, f64),
+    pub precision: Precision,
+}
+
+/// Location of a match, which is malformed without coordinates.
+fn location(
+    lng: Option<f64>,
+    lat: Option<f64>,
+    precision: Precision,
+    json: &serde_json::Value,
+) -> Result<Option<Location>, GeocodeError> {
+    match (lng, lat) {
+        (Some(lng), Some(lat)) => Ok(Some(Location {
+            coordinates: (lng, lat),
+            precision,
+        })),
+        _ => Err(GeocodeError::MalformedResponse(excerpt(&json.to_string()))),
     }
 }
 
-// pub fn geocode_request(app_id: &str, api_key: &str) -> ClonableRequestBuilder {
-//     let url = "https://api.traveltimeapp.com/v4/geocoding/search";
-//     let geocode_client = Client::new();
-//     let geocode_request = geocode_client
-//         .get(url)
-//         .header("X-Application-Id", app_id)
-//         .header("X-Api-Key", api_key)
-//         .header("Accept-Language", "en-US");
-//     ClonableRequestBuilder(geocode_request)
-// }
+/// A geocoding service, turning an address into coordinates.
+pub trait Geocoder {
+    /// Name recorded along with the coordinates it produces.
+    fn name(&self) -> &'static str;
 
-pub fn geocode_request(app_id: &str, api_key: &str) -> ClonableRequestBuilder {
-    let url = "https://geocode.googleapis.com/v4beta/geocode/address";
-    let geocode_client = Client::new();
-    let geocode_request = geocode_client
-        .get(url)
-        .header("X-Goog-Api-Key", api_key)
-        .header("X-Goog-FieldMask", "results.location");
-    ClonableRequestBuilder(geocode_request)
+    /// Location of the best match for `address`, if any.
+    fn locate<'a>(
+        &'a self,
+        address: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<Location>, Error>>;
 }
 
-pub async fn geocode(address: &str, request: RequestBuilder) -> Result<(f64, f64), Error> {
-    match backend::get_coords(address.to_string()).await {
-        Err(_) => {
-            let text = request
-                // .query(&[("query", address)])
-                .query(&[("addressQuery", address)])
-                .send()
-                .await?
-                .text()
-                .await?;
-            let json: serde_json::Value = serde_json::from_str(&text)?;
+/// A geocoder behind a web API.
+pub trait WebGeocoder {
+    fn name(&self) -> &'static str;
+
+    /// The request looking up `address`.
+    fn request(&self, client: &Client, address: &str) -> RequestBuilder;
+
+    /// Location of the best match in the response, if any.
+    ///
+    /// Services that do not tell how precise they are claim to be exact, and are only as
+    /// precise as the address asked for.
+    fn parse(&self, json: &serde_json::Value) -> Result<Option<Location>, GeocodeError>;
+
+    /// The error told by the body of a response, for services not telling it by the status.
+    fn error(&self, _json: &serde_json::Value) -> Option<GeocodeError> {
+        None
+    }
+
+    /// The turn to wait for before each request, for services allowing one per second.
+    fn turn(&self) -> Option<&Mutex<()>> {
+        None
+    }
+}
+
+impl<T: WebGeocoder> Geocoder for T {
+    fn name(&self) -> &'static str {
+        WebGeocoder::name(self)
+    }
+
+    fn locate<'a>(
+        &'a self,
+        address: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<Location>, Error>> {
+        async move {
+            if let Some(turn) = self.turn() {
+                crate::scrape::pace(turn, Duration::from_secs(1)).await;
+            }
+            let response = self.request(&Client::new(), address).send().await?;
+            // the service failing is not the request's fault
+            if response.status().is_server_error() {
+                return Err(response.error_for_status().unwrap_err().into());
+            }
+            let status = response.status();
+            let json = response_json(self, status, &response.text().await?)?;
+            Ok(self.parse(&json)?)
+        }
+        .boxed_local()
+    }
+}
+
+/// The body of a response of `geocoder`, unless the body or the status tell an error.
+fn response_json(
+    geocoder: &impl WebGeocoder,
+    status: StatusCode,
+    text: &str,
+) -> Result<serde_json::Value, GeocodeError> {
+    let json = serde_json::from_str::<serde_json::Value>(text).ok();
+    if let Some(error) = json.as_ref().and_then(|json| geocoder.error(json)) {
+        return Err(error);
+    }
+    match status {
+        StatusCode::TOO_MANY_REQUESTS => Err(GeocodeError::QuotaExceeded),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(GeocodeError::AuthFailure),
+        status if !status.is_success() => Err(GeocodeError::Rejected(format!(
+            "{status}: {}",
+            excerpt(text)
+        ))),
+        _ => json.ok_or_else(|| GeocodeError::MalformedResponse(excerpt(text))),
+    }
+}
+
+/// Precision of a Google place type, knowing that in Japan the 2nd to 4th sublocality levels
+/// are the chome, the block and the building.
+fn google_precision(place_type: &str) -> Option<Precision> {
+    match place_type {
+        "premise" | "subpremise" | "street_address" | "sublocality_level_4" => {
+            Some(Precision::Exact)
+        }
+        "sublocality_level_3" => Some(Precision::Block),
+        "sublocality_level_2" | "sublocality_level_1" | "neighborhood" => Some(Precision::Chome),
+        "locality" | "administrative_area_level_2" => Some(Precision::City),
+        "administrative_area_level_1" => Some(Precision::Prefecture),
+        _ => None,
+    }
+}
+
+/// Google's Geocoding API v4.
+pub struct Google {
+    api_key: String,
+}
+
+impl WebGeocoder for Google {
+    fn name(&self) -> &'static str {
+        "google"
+    }
+
+    fn request(&self, client: &Client, address: &str) -> RequestBuilder {
+        client
+            .get("https://geocode.googleapis.com/v4beta/geocode/address")
+            .header("X-Goog-Api-Key", &self.api_key)
+            .header("X-Goog-FieldMask", "results.location,results.types")
+            .query(&[("addressQuery", address)])
+    }
+
+    fn parse(&self, json: &serde_json::Value) -> Result<Option<Location>, GeocodeError> {
+        let Some(result) = first(&json["results"]) else {
+            return Ok(None);
+        };
+        let precision = result["types"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|place_type| google_precision(place_type.as_str()?))
+            .max()
+            .unwrap_or(Precision::Exact);
+        let coordinates = &result["location"];
+        location(
+            coordinates["longitude"].as_f64(),
+            coordinates["latitude"].as_f64(),
+            precision,
+            json,
+        )
+    }
+
+    /// Errors come as `{"error": {"status": ...}}`, or as the `status` of the older API, which
+    /// answers an invalid key with 400 and a quota exceeded with 200.
+    fn error(&self, json: &serde_json::Value) -> Option<GeocodeError> {
+        let error = &json["error"];
+        let status = error["status"].as_str().or(json["status"].as_str())?;
+        let message = error["message"]
+            .as_str()
+            .or(json["error_message"].as_str())
+            .unwrap_or(status);
+        let reasons = error["details"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|detail| detail["reason"].as_str())
+            .collect::<Vec<_>>();
+        match status {
+            "OK" | "ZERO_RESULTS" => None,
+            "REQUEST_DENIED" | "PERMISSION_DENIED" | "UNAUTHENTICATED" => {
+                Some(GeocodeError::AuthFailure)
+            }
+            _ if reasons.contains(&"API_KEY_INVALID") => Some(GeocodeError::AuthFailure),
+            "OVER_QUERY_LIMIT" | "OVER_DAILY_LIMIT" | "RESOURCE_EXHAUSTED" => {
+                Some(GeocodeError::QuotaExceeded)
+            }
+            _ => Some(GeocodeError::Rejected(message.to_string())),
+        }
+    }
+}
+
+/// TravelTime's geocoder, answering in GeoJSON.
+pub struct TravelTime {
+    app_id: String,
+    api_key: String,
+}
+
+impl WebGeocoder for TravelTime {
+    fn name(&self) -> &'static str {
+        "traveltime"
+    }
+
+    fn request(&self, client: &Client, address: &str) -> RequestBuilder {
+        client
+            .get("https://api.traveltimeapp.com/v4/geocoding/search")
+            .header("X-Application-Id", &self.app_id)
+            .header("X-Api-Key", &self.api_key)
+            .header("Accept-Language", "en-US")
+            .query(&[("query", address)])
+    }
+
+    fn parse(&self, json: &serde_json::Value) -> Result<Option<Location>, GeocodeError> {
+        let Some(feature) = first(&json["features"]) else {
+            return Ok(None);
+        };
+        let point = &feature["geometry"]["coordinates"];
+        location(point[0].as_f64(), point[1].as_f64(), Precision::Exact, json)
+    }
+}
+
+/// Any Nominatim-compatible service, such as OpenStreetMap's or a self-hosted one.
+pub struct Nominatim {
+    endpoint: String,
+    /// Shared by the pages geocoded concurrently.
+    turn: Mutex<()>,
+}
+
+impl WebGeocoder for Nominatim {
+    fn name(&self) -> &'static str {
+        "nominatim"
+    }
+
+    fn request(&self, client: &Client, address: &str) -> RequestBuilder {
+        client
+            .get(format!("{}/search", self.endpoint.trim_end_matches('/')))
+            .query(&[
+                ("q", address),
+                ("format", "jsonv2"),
+                ("limit", "1"),
+                ("countrycodes", "jp"),
+            ])
+    }
+
+    fn parse(&self, json: &serde_json::Value) -> Result<Option<Location>, GeocodeError> {
+        let Some(place) = first(json) else {
+            return Ok(None);
+        };
+        // coordinates come as strings
+        let number = |key: &str| place[key].as_str()?.parse().ok();
+        // 30 for buildings, 26 for streets, 16 for cities and 8 for prefectures
+        let precision = match place["place_rank"].as_u64() {
+            Some(30..) => Precision::Exact,
+            Some(26..=29) => Precision::Block,
+            Some(18..=25) => Precision::Chome,
+            Some(12..=17) => Precision::City,
+            Some(_) => Precision::Prefecture,
+            None => Precision::Exact,
+        };
+        location(number("lon"), number("lat"), precision, json)
+    }
+
+    /// Only the default service is paced, self-hosted ones set their own limits.
+    fn turn(&self) -> Option<&Mutex<()>> {
+        (self.endpoint.trim_end_matches('/') == NOMINATIM).then_some(&self.turn)
+    }
+}
+
+/// The address search of the Geospatial Information Authority of Japan (国土地理院), free and
+/// without a key.
+pub struct Gsi;
 
-            // let coordinates = &json["features"][0]["geometry"]["coordinates"];
-            // let lng = coordinates[0].as_f64().unwrap();
-            // let lat = coordinates[1].as_f64().unwrap();
-            let coordinates = &json["results"][0]["location"];
-            let lng = coordinates["longitude"].as_f64().unwrap();
-            let lat = coordinates["latitude"].as_f64().unwrap();
+impl WebGeocoder for Gsi {
+    fn name(&self) -> &'static str {
+        "gsi"
+    }
+
+    fn request(&self, client: &Client, address: &str) -> RequestBuilder {
+        client
+            .get("https://msearch.gsi.go.jp/address-search/AddressSearch")
+            .query(&[("q", address)])
+    }
+
+    fn parse(&self, json: &serde_json::Value) -> Result<Option<Location>, GeocodeError> {
+        let Some(place) = first(json) else {
+            return Ok(None);
+        };
+        // the address matched, e.g. 東京都港区六本木二丁目
+        let precision = place["properties"]["title"]
+            .as_str()
+            .map_or(Precision::Exact, |title| {
+                addresses::precision(&normalize(title))
+            });
+        let point = &place["geometry"]["coordinates"];
+        location(point[0].as_f64(), point[1].as_f64(), precision, json)
+    }
+}
+
+/// The 位置参照情報 (address reference data) imported with `import-addresses`, needing neither
+/// a key nor a network.
+pub struct Offline;
+
+impl Geocoder for Offline {
+    fn name(&self) -> &'static str {
+        "offline"
+    }
+
+    fn locate<'a>(
+        &'a self,
+        address: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<Location>, Error>> {
+        async move { Ok(backend::locate_offline(address.to_string()).await?) }.boxed_local()
+    }
+}
+
+/// The geocoding providers to choose from in the settings.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum Provider {
+    #[default]
+    Google,
+    TravelTime,
+    Nominatim,
+    Gsi,
+    Offline,
+}
+
+impl Provider {
+    pub const ALL: [Provider; 5] = [
+        Provider::Google,
+        Provider::TravelTime,
+        Provider::Nominatim,
+        Provider::Gsi,
+        Provider::Offline,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Provider::Google => "Google",
+            Provider::TravelTime => "TravelTime",
+            Provider::Nominatim => "Nominatim",
+            Provider::Gsi => "国土地理院 (GSI)",
+            Provider::Offline => "Offline (位置参照情報)",
+        }
+    }
+}
+
+/// Default Nominatim service, whose usage policy allows one request per second.
+const NOMINATIM: &str = "https://nominatim.openstreetmap.org";
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct GeocoderSettings {
+    pub provider: Provider,
+    /// Base URL of the Nominatim service.
+    pub endpoint: String,
+}
+
+impl Default for GeocoderSettings {
+    fn default() -> Self {
+        GeocoderSettings {
+            provider: Provider::default(),
+            endpoint: NOMINATIM.to_string(),
+        }
+    }
+}
 
-            backend::set_coords(address.to_string(), lng, lat).await?;
+impl GeocoderSettings {
+    /// The chosen geocoder, with the credentials it needs.
+    pub fn geocoder(&self, app_id: &str, api_key: &str) -> Box<dyn Geocoder> {
+        match self.provider {
+            Provider::Google => Box::new(Google {
+                api_key: api_key.to_string(),
+            }),
+            Provider::TravelTime => Box::new(TravelTime {
+                app_id: app_id.to_string(),
+                api_key: api_key.to_string(),
+            }),
+            Provider::Nominatim => Box::new(Nominatim {
+                endpoint: self.endpoint.clone(),
+                turn: Mutex::new(()),
+            }),
+            Provider::Gsi => Box::new(Gsi),
+            Provider::Offline => Box::new(Offline),
+        }
+    }
+}
 
-            Ok((lng, lat))
+/// Location of `address`, from the cache or else from `geocoder`, which is given the
+/// normalized address.
+///
+/// Addresses that `geocoder` did not find are remembered for a while, rather than paid for
+/// again at every search.
+pub async fn geocode(address: &str, geocoder: &dyn Geocoder) -> Result<Location, Error> {
+    let address = normalize(address);
+    if let Ok(location) = backend::get_coords(address.clone()).await {
+        return Ok(location);
+    }
+    if backend::is_unresolved(address.clone(), geocoder.name().to_string()).await? {
+        return Err(GeocodeError::NotFound(address).into());
+    }
+    match geocoder.locate(&address).await? {
+        Some(mut location) => {
+            // no match is more precise than the address itself
+            location.precision = location.precision.min(addresses::precision(&address));
+            backend::set_coords(address, location, geocoder.name().to_string()).await?;
+            Ok(location)
+        }
+        None => {
+            backend::set_unresolved(address.clone(), geocoder.name().to_string()).await?;
+            Err(GeocodeError::NotFound(address).into())
         }
-        Ok(ok) => Ok(ok),
     }
 }
 
@@ -81,11 +494,41 @@ fn next_monday() -> String {
     next.to_rfc3339()
 }
 
+/// Four points on the edge of the uncertainty area of an imprecisely located building, keyed in
+/// the cache by the address and their coordinates.
+fn uncertainty_points(building: &Building) -> Vec<(String, Option<(f64, f64)>)> {
+    let (Some((lng, lat)), Some(precision)) = (building.coordinates, building.precision) else {
+        return vec![];
+    };
+    if precision.radius() == 0.0 {
+        return vec![];
+    }
+    // degrees of latitude, and of longitude at this latitude
+    let dlat = precision.radius() / 111_320.0;
+    let dlng = dlat / lat.to_radians().cos();
+    [(0.0, dlat), (dlng, 0.0), (0.0, -dlat), (-dlng, 0.0)]
+        .into_iter()
+        .map(|(x, y)| {
+            let (lng, lat) = (lng + x, lat + y);
+            (
+                format!("{}@{lng:.5},{lat:.5}", building.address),
+                Some((lng, lat)),
+            )
+        })
+        .collect()
+}
+
+/// Travel times from each criterion to the buildings within reach, cached by address.
+///
+/// With `ranges`, imprecisely located buildings are also routed to the edge of their
+/// uncertainty area, and get the range of times found. They are then within reach when any of
+/// those points is.
 pub async fn get_travel_time(
-    app_id: &str,
+    _app_id: &str,
     api_key: &str,
     buildings: &mut [Building],
     criteria: &[Criterion],
+    ranges: bool,
 ) -> Result<(), Error> {
     let url = "https://routes.googleapis.com/distanceMatrix/v2:computeRouteMatrix";
     let client = Client::new();
@@ -94,11 +537,17 @@ pub async fn get_travel_time(
         "originIndex,destinationIndex,duration,condition",
     );
 
-    let set_time = |building: &mut Building, i: usize, criterion: &Criterion, time: usize| {
-        if time <= criterion.time * 60 {
-            building.times.insert(i, (criterion.clone(), time));
-        }
-    };
+    // the points of each building, starting with its coordinates
+    let points = buildings
+        .iter()
+        .map(|building| {
+            let mut points = vec![(building.address.clone(), building.coordinates)];
+            if ranges {
+                points.extend(uncertainty_points(building));
+            }
+            points
+        })
+        .collect::<Vec<_>>();
 
     for (i, criterion) in criteria.iter().enumerate() {
         let origin = serde_json::json!(
@@ -119,31 +568,42 @@ pub async fn get_travel_time(
             TransportationMode::Public => (100, "TRANSIT"),
         };
 
-        let mut new_buildings = vec![];
-        for building in buildings.iter_mut() {
-            match backend::get_time(
-                criterion.address.clone(),
-                building.address.clone(),
-                criterion.mode.clone(),
-            )
-            .await
-            {
-                Ok(time) => set_time(building, i, criterion, time),
-                Err(_) => new_buildings.push(building),
+        let mut times = points
+            .iter()
+            .map(|points| vec![None; points.len()])
+            .collect::<Vec<_>>();
+        let mut new_points = vec![];
+        for (b, building_points) in points.iter().enumerate() {
+            for (p, (key, coordinates)) in building_points.iter().enumerate() {
+                match backend::get_time(
+                    criterion.address.clone(),
+                    key.clone(),
+                    criterion.mode.clone(),
+                )
+                .await
+                {
+                    Ok(time) => times[b][p] = Some(time),
+                    // buildings that could not be geocoded cannot be routed either
+                    Err(_) => {
+                        if let Some(coordinates) = coordinates {
+                            new_points.push((b, p, *coordinates));
+                        }
+                    }
+                }
             }
         }
 
-        for buildings_batch in new_buildings.chunks_mut(limit) {
-            let destinations = buildings_batch
+        for points_batch in new_points.chunks(limit) {
+            let destinations = points_batch
                 .iter()
-                .map(|building| {
+                .map(|(_, _, (lng, lat))| {
                     serde_json::json!(
                     {
                         "waypoint": {
                             "location": {
                                 "latLng": {
-                                    "latitude": building.coordinates.1,
-                                    "longitude": building.coordinates.0
+                                    "latitude": lat,
+                                    "longitude": lng
                                 }
                             }
                         }
@@ -178,15 +638,31 @@ pub async fn get_travel_time(
                         .parse::<f64>()
                         .unwrap() as usize;
                     let j = route["destinationIndex"].as_u64().unwrap() as usize;
-                    let building = &mut buildings_batch[j];
+                    let (b, p, _) = points_batch[j];
                     backend::set_time(
                         criterion.address.clone(),
-                        building.address.clone(),
+                        points[b][p].0.clone(),
                         criterion.mode.clone(),
                         time,
                     )
                     .await?;
-                    set_time(building, i, criterion, time)
+                    times[b][p] = Some(time);
+                }
+            }
+        }
+
+        for (building, times) in buildings.iter_mut().zip(times) {
+            let Some(time) = times[0] else {
+                continue;
+            };
+            let (min, max) = times
+                .iter()
+                .flatten()
+                .fold((time, time), |(min, max), &t| (min.min(t), max.max(t)));
+            if min <= criterion.time * 60 {
+                building.times.insert(i, (criterion.clone(), time));
+                if min < max {
+                    building.time_ranges.insert(i, (min, max));
                 }
             }
         }
@@ -301,3 +777,158 @@ pub async fn get_travel_time(
 
 //     Ok(())
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn google() -> Google {
+        Google {
+            api_key: "key".to_string(),
+        }
+    }
+
+    fn nominatim() -> Nominatim {
+        Nominatim {
+            endpoint: NOMINATIM.to_string(),
+            turn: Mutex::new(()),
+        }
+    }
+
+    fn json(text: &str) -> serde_json::Value {
+        serde_json::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn tells_errors_from_google_bodies() {
+        for (status, body, error) in [
+            (
+                StatusCode::BAD_REQUEST,
+                r#"{"error": {"code": 400, "message": "API key not valid.", "status": "INVALID_ARGUMENT",
+                    "details": [{"reason": "API_KEY_INVALID"}]}}"#,
+                GeocodeError::AuthFailure,
+            ),
+            (
+                StatusCode::OK,
+                r#"{"results": [], "status": "REQUEST_DENIED", "error_message": "invalid key"}"#,
+                GeocodeError::AuthFailure,
+            ),
+            (
+                StatusCode::OK,
+                r#"{"results": [], "status": "OVER_QUERY_LIMIT"}"#,
+                GeocodeError::QuotaExceeded,
+            ),
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                r#"{"error": {"code": 429, "message": "Quota exceeded", "status": "RESOURCE_EXHAUSTED"}}"#,
+                GeocodeError::QuotaExceeded,
+            ),
+            (
+                StatusCode::BAD_REQUEST,
+                r#"{"error": {"code": 400, "message": "bad address", "status": "INVALID_ARGUMENT"}}"#,
+                GeocodeError::Rejected("bad address".to_string()),
+            ),
+        ] {
+            assert_eq!(response_json(&google(), status, body), Err(error), "{body}");
+        }
+    }
+
+    #[test]
+    fn tells_errors_from_statuses() {
+        for (status, error) in [
+            (StatusCode::TOO_MANY_REQUESTS, GeocodeError::QuotaExceeded),
+            (StatusCode::FORBIDDEN, GeocodeError::AuthFailure),
+            (StatusCode::UNAUTHORIZED, GeocodeError::AuthFailure),
+            (
+                StatusCode::NOT_FOUND,
+                GeocodeError::Rejected("404 Not Found: Not Found".to_string()),
+            ),
+        ] {
+            assert_eq!(response_json(&nominatim(), status, "Not Found"), Err(error));
+        }
+        assert_eq!(
+            response_json(&Gsi, StatusCode::OK, "<html>"),
+            Err(GeocodeError::MalformedResponse("<html>".to_string()))
+        );
+    }
+
+    #[test]
+    fn finds_nothing_in_empty_results() {
+        for body in [r#"{"results": [], "status": "ZERO_RESULTS"}"#, "{}"] {
+            let json = response_json(&google(), StatusCode::OK, body).unwrap();
+            assert_eq!(google().parse(&json), Ok(None));
+        }
+        assert_eq!(nominatim().parse(&json("[]")), Ok(None));
+        assert_eq!(Gsi.parse(&json("[]")), Ok(None));
+    }
+
+    #[test]
+    fn rejects_results_without_coordinates() {
+        let result = google().parse(&json(r#"{"results": [{"types": ["premise"]}]}"#));
+        assert!(matches!(result, Err(GeocodeError::MalformedResponse(_))));
+        let result = nominatim().parse(&json(r#"[{"lat": "x", "lon": "139.7"}]"#));
+        assert!(matches!(result, Err(GeocodeError::MalformedResponse(_))));
+    }
+
+    #[test]
+    fn surrounds_imprecise_buildings() {
+        let mut building = crate::sources::new_building(
+            "ビル".to_string(),
+            "東京都港区六本木2".to_string(),
+            vec![],
+            &[] as &[&str],
+            vec![],
+        );
+        assert!(uncertainty_points(&building).is_empty());
+        building.coordinates = Some((139.73, 35.66));
+        for (precision, count) in [
+            (Precision::Prefecture, 4),
+            (Precision::City, 4),
+            (Precision::Chome, 4),
+            (Precision::Block, 4),
+            (Precision::Exact, 0),
+        ] {
+            building.precision = Some(precision);
+            let points = uncertainty_points(&building);
+            assert_eq!(points.len(), count, "{precision:?}");
+            for (key, coordinates) in points {
+                assert!(key.starts_with("東京都港区六本木2@"), "{key}");
+                let (lng, lat) = coordinates.unwrap();
+                let (x, y) = ((lng - 139.73) * 35.66_f64.to_radians().cos(), lat - 35.66);
+                let distance = x.hypot(y) * 111_320.0;
+                assert!((distance - precision.radius()).abs() < 0.01, "{distance}");
+            }
+        }
+    }
+
+    #[test]
+    fn parses_locations() {
+        let location = google()
+            .parse(&json(
+                r#"{"results": [{"location": {"latitude": 35.66, "longitude": 139.73},
+                    "types": ["political", "sublocality_level_2"]}]}"#,
+            ))
+            .unwrap()
+            .unwrap();
+        assert_eq!(location.coordinates, (139.73, 35.66));
+        assert_eq!(location.precision, Precision::Chome);
+
+        let location = nominatim()
+            .parse(&json(
+                r#"[{"lat": "35.66", "lon": "139.73", "place_rank": 26}]"#,
+            ))
+            .unwrap()
+            .unwrap();
+        assert_eq!(location.coordinates, (139.73, 35.66));
+        assert_eq!(location.precision, Precision::Block);
+
+        let location = Gsi
+            .parse(&json(
+                r#"[{"geometry": {"coordinates": [139.73, 35.66]},
+                    "properties": {"title": "東京都港区六本木二丁目"}}]"#,
+            ))
+            .unwrap()
+            .unwrap();
+        assert_eq!(location.precision, Precision::Chome);
+    }
+}
@@ -1,16 +1,27 @@
 use std::{
     collections::HashMap,
+    fmt,
     num::{ParseFloatError, ParseIntError},
+    str::FromStr,
 };
 
+use chrono::Datelike;
 use dioxus::html::{FormData, FormValue};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+pub mod addresses;
 pub mod backend;
 pub mod components;
 mod geocode;
 mod scrape;
+mod search;
+// saved pages are only parsed by the server, and live ones by the browser with the `proxy` feature
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+mod sources;
+
+/// The revised seismic code (新耐震基準) applies to buildings permitted from June 1981.
+const SEISMIC_CODE_YEAR: i32 = 1981;
 
 const SUUMOURL: &str = "https://suumo.jp/jj/chintai/ichiran/FR301FC001/?ar=030&bs=040&fw2=&pc=50&po1=25&po2=99&ta=13&sc=13103&sc=13104&sc=13113&sc=13110&sc=13112&md=03&md=04&md=05&md=06&md=07&md=08&md=09&md=10&md=11&md=12&md=13&md=14&cb=0.0&ct=20.0&et=9999999&mb=40&mt=9999999&cn=9999999&tc=0400501&tc=0400601&tc=0400301&tc=0400203&tc=0400902&tc=0400907&shkr1=03&shkr2=03&shkr3=03&shkr4=03";
 
@@ -20,14 +31,21 @@ pub enum Error {
     ServerError(#[from] dioxus::CapturedError),
     #[error("reqwest error: {0}")]
     Reqwest(#[from] reqwest::Error),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
     #[error("serde_json error: {0}")]
     SerdeJSON(#[from] serde_json::Error),
     #[error("parse error: {0}")]
     ParseInt(#[from] ParseIntError),
     #[error("parse error: {0}")]
     ParseFloat(#[from] ParseFloatError),
+    #[cfg(feature = "server")]
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
     #[error("selector error: {0}")]
     Scrape(String),
+    #[error("geocoding error: {0}")]
+    Geocode(#[from] geocode::GeocodeError),
     #[error("misc error: {0}")]
     Misc(String),
 }
@@ -44,28 +62,499 @@ impl From<dioxus::prelude::ServerFnError> for Error {
     }
 }
 
-#[derive(Clone, PartialEq)]
+/// An amount as listed, e.g. `12.5万円`, `8000円` or `1ヶ月`.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Price {
+    text: String,
+    yen: u64,
+    /// Set when the amount is given as a multiple of the monthly rent.
+    months: Option<f64>,
+}
+
+/// A floor plan (間取り) such as `1LDK` or `2SLDK`, ordered from the smallest.
+///
+/// Rooms come first, so `2K` is larger than `1LDK`, and `1LDK` larger than `1K`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub struct Layout {
+    rooms: usize,
+    living: bool,
+    dining: bool,
+    kitchen: bool,
+    /// Service room (納戸).
+    storage: bool,
+}
+
+impl FromStr for Layout {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        if text == "ワンルーム" || text == "1R" {
+            return Ok(Layout {
+                rooms: 1,
+                living: false,
+                dining: false,
+                kitchen: false,
+                storage: false,
+            });
+        }
+        let digits = text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len());
+        let (rooms, kind) = text.split_at(digits);
+        let rooms = rooms.parse()?;
+        if kind.is_empty() || !kind.chars().all(|c| "SLDK".contains(c)) {
+            return Err(Error::Scrape(format!("invalid layout {text}")));
+        }
+        Ok(Layout {
+            rooms,
+            living: kind.contains('L'),
+            dining: kind.contains('D'),
+            kitchen: kind.contains('K'),
+            storage: kind.contains('S'),
+        })
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !(self.living || self.dining || self.kitchen || self.storage) {
+            return write!(f, "ワンルーム");
+        }
+        write!(f, "{}", self.rooms)?;
+        for (flag, letter) in [
+            (self.storage, 'S'),
+            (self.living, 'L'),
+            (self.dining, 'D'),
+            (self.kitchen, 'K'),
+        ] {
+            if flag {
+                write!(f, "{letter}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The floor of a unit, e.g. `3階`, `B1階`, or `5-6階` for maisonettes.
+///
+/// Basements are negative, and there is no floor 0.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Floor {
+    lowest: i32,
+    highest: i32,
+}
+
+impl FromStr for Floor {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let floors = text
+            .strip_suffix('階')
+            .ok_or(Error::Scrape(format!("invalid floor {text}")))?;
+        let floor = |floor: &str| match floor.strip_prefix('B') {
+            Some(basement) => basement.parse::<i32>().map(|basement| -basement),
+            None => floor.parse::<i32>(),
+        };
+        let (lowest, highest) = match floors.split_once('-') {
+            Some((lowest, highest)) => (floor(lowest)?, floor(highest)?),
+            None => (floor(floors)?, floor(floors)?),
+        };
+        Ok(Floor { lowest, highest })
+    }
+}
+
+impl fmt::Display for Floor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let floor = |floor: i32| {
+            if floor < 0 {
+                format!("B{}", -floor)
+            } else {
+                floor.to_string()
+            }
+        };
+        if self.lowest == self.highest {
+            write!(f, "{}F", floor(self.lowest))
+        } else {
+            write!(f, "{}-{}F", floor(self.lowest), floor(self.highest))
+        }
+    }
+}
+
+/// How an apartment changed since the previous search.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum ListingStatus {
+    #[default]
+    Unchanged,
+    New,
+    /// Differences in yen of the rent and fees.
+    Changed {
+        rent: i64,
+        fees: i64,
+    },
+    Gone,
+}
+
+/// Facilities that can be filtered on, as written on detail pages.
+const FACILITIES: [&str; 3] = ["オートロック", "宅配ボックス", "バス・トイレ別"];
+
+/// Whether the lease can be renewed (普通借家) or ends at a fixed term (定期借家).
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Lease {
+    Regular,
+    FixedTerm,
+}
+
+/// Attributes only given on the detail page of an apartment.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct ApartmentDetails {
+    /// Facing direction (向き), e.g. `南東`.
+    orientation: Option<String>,
+    /// Move-in date (入居), e.g. `即入居可`.
+    move_in: Option<String>,
+    lease: Option<Lease>,
+    facilities: Vec<String>,
+    photos: Vec<String>,
+}
+
+impl ApartmentDetails {
+    fn has_facility(&self, facility: &str) -> bool {
+        // listings write both バス・トイレ別 and バストイレ別
+        let facility = facility.replace('・', "");
+        self.facilities
+            .iter()
+            .any(|other| other.replace('・', "").contains(&facility))
+    }
+}
+
+/// Another listing of the same unit, by a different agency.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Alternative {
+    id: u64,
+    url: String,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Apartment {
-    rent: String,
-    fees: Option<String>,
+    rent: Price,
+    fees: Option<Price>,
     id: u64,
-    deposit: Option<String>,
-    key_money: Option<String>,
-    kind: String,
-    area: String,
+    deposit: Option<Price>,
+    key_money: Option<Price>,
+    layout: Layout,
+    /// Floor area in square meters.
+    area: f64,
+    floor: Option<Floor>,
     plan: String,
     url: String,
+    #[serde(default)]
+    status: ListingStatus,
+    #[serde(default)]
+    details: Option<ApartmentDetails>,
+    #[serde(default)]
+    alternatives: Vec<Alternative>,
+    /// Names of the saved searches that found this unit.
+    #[serde(default)]
+    searches: Vec<String>,
+}
+
+impl Apartment {
+    /// SUUMO ids of all the listings of this unit.
+    #[cfg(feature = "server")]
+    fn ids(&self) -> Vec<u64> {
+        std::iter::once(self.id)
+            .chain(self.alternatives.iter().map(|alternative| alternative.id))
+            .collect()
+    }
+
+    /// Whether `other` is most likely the same unit, listed by another agency.
+    ///
+    /// Units of unknown floor are never merged, as they may be on any floor.
+    fn same_unit(&self, other: &Apartment) -> bool {
+        self.floor.is_some()
+            && self.floor == other.floor
+            && self.area == other.area
+            && self.layout == other.layout
+            && self.rent.yen == other.rent.yen
+    }
+
+    /// Rent and management fees.
+    fn monthly(&self) -> u64 {
+        self.rent.yen + self.fees.as_ref().map_or(0, |fees| fees.yen)
+    }
+
+    fn rent_per_area(&self) -> u64 {
+        (self.rent.yen as f64 / self.area).round() as u64
+    }
+
+    /// Deposit, key money and the first month.
+    fn initial(&self) -> u64 {
+        self.monthly()
+            + self.deposit.as_ref().map_or(0, |deposit| deposit.yen)
+            + self.key_money.as_ref().map_or(0, |key_money| key_money.yen)
+    }
+}
+
+/// Merges the buildings listed several times, and the units listed by several agencies.
+fn deduplicate(buildings: Vec<Building>) -> Vec<Building> {
+    let mut merged: Vec<Building> = vec![];
+    for mut building in buildings {
+        let apartments = std::mem::take(&mut building.apartments);
+        let k = match merged
+            .iter()
+            .position(|other| other.name == building.name && other.address == building.address)
+        {
+            Some(k) => k,
+            None => {
+                merged.push(building);
+                merged.len() - 1
+            }
+        };
+        for apartment in apartments {
+            merged[k].add_apartment(apartment);
+        }
+    }
+    merged
 }
 
-#[derive(Clone, PartialEq)]
+/// Formats `yen` with thousands separators, e.g. `¥125,000`.
+fn format_yen(yen: u64) -> String {
+    let digits = yen.to_string();
+    let mut formatted = String::from("¥");
+    for (k, digit) in digits.chars().enumerate() {
+        if k > 0 && (digits.len() - k).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// One line of the "access" column of a listing, e.g. `都営大江戸線/六本木駅 歩6分`.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct StationAccess {
+    line: String,
+    station: String,
+    /// Walking minutes, to the station or to the bus stop when `bus` is set.
+    walk: Option<usize>,
+    bus: Option<usize>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Building {
     name: String,
     address: String,
-    coordinates: (f64, f64),
+    stations: Vec<StationAccess>,
+    /// Year of construction, estimated from the age given at scraping time.
+    built: Option<i32>,
+    /// Number of floors above ground.
+    floors: Option<usize>,
+    /// Missing when the address could not be geocoded.
+    coordinates: Option<(f64, f64)>,
+    /// Unknown for listings saved before it was recorded.
+    #[serde(default)]
+    precision: Option<geocode::Precision>,
     times: HashMap<usize, (Criterion, usize)>,
+    /// Shortest and longest times over the uncertainty area, when they differ.
+    #[serde(default)]
+    time_ranges: HashMap<usize, (usize, usize)>,
     apartments: Vec<Apartment>,
 }
 
+impl Building {
+    /// Walking minutes to the closest station reachable on foot.
+    fn station_walk(&self) -> Option<usize> {
+        self.stations
+            .iter()
+            .filter(|access| access.bus.is_none())
+            .filter_map(|access| access.walk)
+            .min()
+    }
+
+    /// The status shared by all apartments, if any, e.g. for a building that is new.
+    fn status(&self) -> Option<ListingStatus> {
+        let status = self.apartments.first()?.status;
+        self.apartments
+            .iter()
+            .all(|apartment| apartment.status == status)
+            .then_some(status)
+    }
+
+    /// Adds `apartment`, as an alternative listing if the unit is already there.
+    fn add_apartment(&mut self, apartment: Apartment) {
+        match self
+            .apartments
+            .iter_mut()
+            .find(|other| other.same_unit(&apartment))
+        {
+            Some(other) => {
+                for search in apartment.searches {
+                    if !other.searches.contains(&search) {
+                        other.searches.push(search);
+                    }
+                }
+                // the same listing may be found by several searches
+                let listings = std::iter::once(Alternative {
+                    id: apartment.id,
+                    url: apartment.url,
+                })
+                .chain(apartment.alternatives);
+                for listing in listings {
+                    if listing.id != other.id
+                        && other
+                            .alternatives
+                            .iter()
+                            .all(|alternative| alternative.id != listing.id)
+                    {
+                        other.alternatives.push(listing);
+                    }
+                }
+            }
+            None => self.apartments.push(apartment),
+        }
+    }
+
+    fn age(&self) -> Option<usize> {
+        self.built
+            .map(|built| (chrono::Local::now().year() - built).max(0) as usize)
+    }
+
+    /// Whether the building is within reach of all `criteria`, or cannot be told for lack of
+    /// coordinates, in which case it is listed with a warning.
+    fn within_reach(&self, criteria: &[Criterion]) -> bool {
+        self.coordinates.is_none() || self.times.len() == criteria.len()
+    }
+
+    /// Whether the building may predate the June 1981 seismic code (新耐震基準).
+    ///
+    /// Only the year is known, so buildings from 1981 itself are counted in.
+    fn old_seismic_code(&self) -> bool {
+        self.built.is_some_and(|built| built <= SEISMIC_CODE_YEAR)
+    }
+}
+
+/// A building, apartment or page that was skipped while scraping.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ScrapeProblem {
+    /// Unknown for detail pages.
+    page: Option<usize>,
+    /// Unknown when the building name itself could not be scraped.
+    building: Option<String>,
+    field: String,
+    reason: String,
+}
+
+impl fmt::Display for ScrapeProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.page, &self.building) {
+            (Some(page), Some(building)) => write!(f, "page {page}, {building}: ")?,
+            (Some(page), None) => write!(f, "page {page}: ")?,
+            (None, Some(building)) => write!(f, "{building}: ")?,
+            (None, None) => {}
+        }
+        write!(f, "{} {}", self.field, self.reason)
+    }
+}
+
+/// A named SUUMO search, scraped along with the other enabled ones.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub name: String,
+    pub url: String,
+    pub enabled: bool,
+}
+
+/// Problems encountered during a search, whose other results are still kept.
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct ScrapeReport {
+    problems: Vec<ScrapeProblem>,
+    /// Names of the searches with pages that could not be scraped, whose missing apartments
+    /// may still be listed.
+    incomplete: Vec<String>,
+}
+
+/// Filters on the listings themselves, on top of the travel time criteria.
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct Filters {
+    max_station_walk: Option<usize>,
+    station: String,
+    max_age: Option<usize>,
+    min_floors: Option<usize>,
+    new_seismic_code: bool,
+    max_monthly: Option<u64>,
+    min_layout: Option<Layout>,
+    min_area: Option<f64>,
+    no_ground_floor: bool,
+    min_floor: Option<i32>,
+    orientation: String,
+    regular_lease: bool,
+    facilities: Vec<String>,
+}
+
+impl Filters {
+    /// Returns `building` with only its matching apartments, if it matches and any remain.
+    pub fn apply(&self, building: &Building) -> Option<Building> {
+        if !self.matches(building) {
+            return None;
+        }
+        let apartments = building
+            .apartments
+            .iter()
+            .filter(|apartment| self.matches_apartment(apartment))
+            .cloned()
+            .collect::<Vec<_>>();
+        (!apartments.is_empty()).then(|| Building {
+            apartments,
+            ..building.clone()
+        })
+    }
+
+    fn matches_apartment(&self, apartment: &Apartment) -> bool {
+        let monthly = self
+            .max_monthly
+            .is_none_or(|max| apartment.monthly() <= max);
+        let layout = self.min_layout.is_none_or(|min| apartment.layout >= min);
+        let area = self.min_area.is_none_or(|min| apartment.area >= min);
+        let ground_floor =
+            !self.no_ground_floor || apartment.floor.is_some_and(|floor| floor.lowest != 1);
+        let floor = self
+            .min_floor
+            .is_none_or(|min| apartment.floor.is_some_and(|floor| floor.lowest >= min));
+        let details = apartment.details.as_ref();
+        let orientation = self.orientation.is_empty()
+            || details
+                .and_then(|details| details.orientation.as_ref())
+                .is_some_and(|orientation| orientation.contains(&self.orientation));
+        let lease = !self.regular_lease
+            || details.is_some_and(|details| details.lease == Some(Lease::Regular));
+        let facilities = self
+            .facilities
+            .iter()
+            .all(|facility| details.is_some_and(|details| details.has_facility(facility)));
+        monthly && layout && area && ground_floor && floor && orientation && lease && facilities
+    }
+
+    fn matches(&self, building: &Building) -> bool {
+        let walk = self
+            .max_station_walk
+            .is_none_or(|max| building.station_walk().is_some_and(|walk| walk <= max));
+        let station = self.station.is_empty()
+            || building.stations.iter().any(|access| {
+                access.line.contains(&self.station) || access.station.contains(&self.station)
+            });
+        let age = self
+            .max_age
+            .is_none_or(|max| building.age().is_some_and(|age| age <= max));
+        let floors = self
+            .min_floors
+            .is_none_or(|min| building.floors.is_some_and(|floors| floors >= min));
+        let seismic =
+            !self.new_seismic_code || (building.built.is_some() && !building.old_seismic_code());
+        walk && station && age && floors && seismic
+    }
+}
+
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 enum TransportationMode {
     Cycling,
@@ -103,3 +592,76 @@ pub fn get_string(data: &FormData, field: &str) -> Option<String> {
         .as_ref()
         .and_then(form_value_to_string)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_layouts() {
+        for (text, layout) in [
+            ("ワンルーム", "ワンルーム"),
+            ("1R", "ワンルーム"),
+            ("1K", "1K"),
+            (" 2SLDK ", "2SLDK"),
+        ] {
+            assert_eq!(text.parse::<Layout>().unwrap().to_string(), layout);
+        }
+        for text in ["", "LDK", "2LDKX", "二LDK"] {
+            assert!(text.parse::<Layout>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn orders_layouts() {
+        let layouts: Vec<Layout> = [
+            "ワンルーム",
+            "1K",
+            "1DK",
+            "1LDK",
+            "2K",
+            "2LDK",
+            "2SLDK",
+            "3K",
+        ]
+        .into_iter()
+        .map(|text| text.parse().unwrap())
+        .collect();
+        assert!(layouts.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn parses_floors() {
+        for (text, lowest, highest) in [
+            ("3階", 3, 3),
+            ("B1階", -1, -1),
+            ("5-6階", 5, 6),
+            ("B1-1階", -1, 1),
+        ] {
+            let floor: Floor = text.parse().unwrap();
+            assert_eq!((floor.lowest, floor.highest), (lowest, highest), "{text}");
+        }
+        assert_eq!("B1階".parse::<Floor>().unwrap().to_string(), "B1F");
+        assert_eq!("5-6階".parse::<Floor>().unwrap().to_string(), "5-6F");
+        for text in ["3", "-階", "地下1階", "階"] {
+            assert!(text.parse::<Floor>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn merges_units_of_known_floor() {
+        use sources::ListingSource;
+
+        let doc = scraper::Html::parse_document(include_str!("../suumo.html"));
+        let (buildings, _) = sources::Suumo.parse_page(&doc, 1).unwrap();
+        let apartment = buildings[0].apartments[0].clone();
+        assert!(apartment.floor.is_some());
+        assert!(apartment.same_unit(&apartment.clone()));
+
+        let unknown = Apartment {
+            floor: None,
+            ..apartment
+        };
+        assert!(!unknown.same_unit(&unknown.clone()));
+    }
+}
@@ -8,6 +8,18 @@ const MAIN_CSS: Asset = asset!("/assets/main.css");
 const MAP_JS: Asset = asset!("/assets/map.js", JsAssetOptions::new().with_minify(false));
 
 fn main() {
+    // `import-addresses <file.csv>...` loads address reference data for offline geocoding
+    #[cfg(feature = "server")]
+    if std::env::args().nth(1).as_deref() == Some("import-addresses") {
+        for path in std::env::args().skip(2) {
+            match suumo_traveltime::addresses::import(std::path::Path::new(&path)) {
+                Ok(count) => println!("{path}: {count} places"),
+                Err(error) => eprintln!("{path}: {error}"),
+            }
+        }
+        return;
+    }
+
     dioxus::launch(App);
 }
 
@@ -1,162 +1,516 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf, time::Duration};
 
 use dioxus::prelude::*;
 use dioxus_logger::tracing;
-use reqwest::{Client, RequestBuilder};
-use scraper::{Html, Selector};
+use futures::{StreamExt, TryStreamExt, channel::mpsc::UnboundedSender, lock::Mutex, stream};
+use serde::{Deserialize, Serialize};
 
-use crate::{Apartment, Building, Error, backend, geocode::geocode};
+use crate::{
+    Apartment, ApartmentDetails, Building, Error, ListingStatus, SavedSearch, ScrapeProblem,
+    ScrapeReport, deduplicate,
+    geocode::{Geocoder, geocode},
+    sources::source_for,
+};
 
+/// Where the result pages are read from, on any of the supported portals.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PageSource {
+    /// The live site, fetched by the server, or through corsproxy.io with the `proxy` feature.
+    Live(String),
+    /// A single saved results page, or a directory of them named `1.html`, `2.html`, ...,
+    /// read by the server.
+    Saved(PathBuf),
+}
+
+impl PageSource {
+    /// Interprets `source` as a `file://` URL or a local path, and as a live URL otherwise.
+    pub fn parse(source: &str) -> Self {
+        match source.strip_prefix("file://") {
+            Some(path) => Self::Saved(PathBuf::from(path)),
+            None if source.starts_with("http://") || source.starts_with("https://") => {
+                Self::Live(source.to_string())
+            }
+            None => Self::Saved(PathBuf::from(source)),
+        }
+    }
+
+    /// Fetches and parses one page.
+    async fn scrape_page(&self, page: usize, config: &ScrapeConfig) -> Result<ScrapedPage, Error> {
+        match self {
+            #[cfg(not(feature = "proxy"))]
+            Self::Live(url) => {
+                Ok(crate::backend::scrape_live_page(url.clone(), page, config.clone()).await?)
+            }
+            #[cfg(feature = "proxy")]
+            Self::Live(url) => {
+                let page_url = source_for(url).page_url(url, page);
+                let html = fetch(&proxied(&page_url)?, config).await?;
+                parse_live_page(url, &html, page)
+            }
+            Self::Saved(path) => Ok(crate::backend::scrape_saved_page(path.clone(), page).await?),
+        }
+    }
+}
+
+/// Reads and parses one saved page from `path`, a single page or a directory of numbered pages,
+/// which must be under `dir`.
+#[cfg(feature = "server")]
+pub(crate) fn read_saved_page(
+    dir: &std::path::Path,
+    path: &std::path::Path,
+    page: usize,
+) -> Result<ScrapedPage, Error> {
+    let dir = dir.canonicalize()?;
+    let path = dir.join(path).canonicalize()?;
+    if !path.starts_with(&dir) {
+        return Err(Error::Scrape(format!(
+            "{} is not under {}",
+            path.display(),
+            dir.display()
+        )));
+    }
+    let page_path = |page: usize| path.join(format!("{page}.html"));
+    let (pages, html) = if path.is_dir() {
+        let pages = (1..).take_while(|&page| page_path(page).is_file()).count();
+        (pages, std::fs::read_to_string(page_path(page))?)
+    } else {
+        (1, std::fs::read_to_string(path)?)
+    };
+    let doc = scraper::Html::parse_document(&html);
+    let (buildings, problems) = crate::sources::source_of(&doc).parse_page(&doc, page)?;
+    Ok(ScrapedPage {
+        pages,
+        buildings,
+        problems,
+    })
+}
+
+/// `url` through corsproxy.io.
+#[cfg(feature = "proxy")]
+fn proxied(url: &str) -> Result<String, Error> {
+    Ok(
+        reqwest::Url::parse_with_params("https://corsproxy.io/", &[("url", url)])
+            .map_err(|error| Error::Misc(error.to_string()))?
+            .to_string(),
+    )
+}
+
+/// Waits for `duration`, on the server and in the browser alike.
+async fn sleep(duration: Duration) {
+    #[cfg(target_arch = "wasm32")]
+    gloo_timers::future::sleep(duration).await;
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(duration).await;
+}
+
+/// Spaces out the requests sharing `turn` by `delay`, however many are in flight.
+pub(crate) async fn pace(turn: &Mutex<()>, delay: Duration) {
+    let _turn = turn.lock().await;
+    sleep(delay).await;
+}
+
+/// Whether the request failing with `error` may succeed when sent again.
+#[cfg(any(feature = "server", feature = "proxy"))]
+fn is_transient(error: &reqwest::Error) -> bool {
+    // the browser does not tell connection errors apart
+    #[cfg(not(target_arch = "wasm32"))]
+    if error.is_connect() {
+        return true;
+    }
+    error.is_timeout() || error.is_request()
+}
+
+/// Fetches `url`, retrying rate limiting, server errors and dropped connections with
+/// exponential backoff.
+#[cfg(any(feature = "server", feature = "proxy"))]
+pub(crate) async fn fetch(url: &str, config: &ScrapeConfig) -> Result<String, Error> {
+    let client = reqwest::Client::new();
+    let mut backoff = config.backoff;
+    let mut attempt = 0;
+    loop {
+        let error = match client.get(url).send().await {
+            Ok(response) => {
+                let status = response.status();
+                if !(status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS) {
+                    return Ok(response.error_for_status()?.text().await?);
+                }
+                response.error_for_status().unwrap_err()
+            }
+            Err(error) if is_transient(&error) => error,
+            Err(error) => return Err(error.into()),
+        };
+        if attempt == config.retries {
+            return Err(error.into());
+        }
+        attempt += 1;
+        tracing::warn!(
+            "{url}: {error}, retrying in {backoff:?} ({attempt}/{})",
+            config.retries
+        );
+        sleep(backoff).await;
+        backoff *= 2;
+    }
+}
+
+/// Parses a live result page, along with the number of pages of the search.
+#[cfg(any(feature = "server", feature = "proxy"))]
+pub(crate) fn parse_live_page(url: &str, html: &str, page: usize) -> Result<ScrapedPage, Error> {
+    let source = source_for(url);
+    let doc = scraper::Html::parse_document(html);
+    let (buildings, problems) = source.parse_page(&doc, page)?;
+    Ok(ScrapedPage {
+        pages: source.page_count(&doc)?,
+        buildings,
+        problems,
+    })
+}
+
+/// One parsed result page.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScrapedPage {
+    /// Number of pages of the whole search.
+    pub pages: usize,
+    pub buildings: Vec<Building>,
+    pub problems: Vec<ScrapeProblem>,
+}
+
+/// Fetches the detail page of an apartment, and saves it with the listing.
+async fn fetch_details(
+    apartment: &Apartment,
+    config: &ScrapeConfig,
+) -> Result<ApartmentDetails, Error> {
+    #[cfg(not(feature = "proxy"))]
+    {
+        Ok(
+            crate::backend::get_details(apartment.id, apartment.url.clone(), config.clone())
+                .await?,
+        )
+    }
+    #[cfg(feature = "proxy")]
+    {
+        let html = fetch(&proxied(&apartment.url)?, config).await?;
+        let details = source_for(&apartment.url).parse_details(&html)?;
+        let saving = crate::backend::save_details(apartment.id, details.clone());
+        if let Err(error) = saving.await {
+            tracing::warn!("details of {} not saved: {error}", apartment.url);
+        }
+        Ok(details)
+    }
+}
+
+/// Fills the details of all listed apartments.
+///
+/// Only new and repriced apartments are fetched again, the others keep the details saved with
+/// their listing, if any.
+pub async fn scrape_details(
+    buildings: &mut [Building],
+    config: &ScrapeConfig,
+    mut scrape_progress: Signal<f64>,
+) -> Vec<ScrapeProblem> {
+    let apartments = buildings
+        .iter_mut()
+        .flat_map(|building| {
+            let name = &building.name;
+            building
+                .apartments
+                .iter_mut()
+                .filter(|apartment| apartment.status != ListingStatus::Gone)
+                .filter(|apartment| source_for(&apartment.url).has_details())
+                .filter(|apartment| {
+                    apartment.details.is_none()
+                        || matches!(
+                            apartment.status,
+                            ListingStatus::New | ListingStatus::Changed { .. }
+                        )
+                })
+                .map(move |apartment| (name, apartment))
+        })
+        .collect::<Vec<_>>();
+    let count = apartments.len();
+    scrape_progress.set(0.0);
+    let turn = Mutex::new(());
+
+    stream::iter(apartments)
+        .map(|(name, apartment)| {
+            let turn = &turn;
+            async move {
+                // the apartments whose details are kept were left out, so all of these are fetched
+                pace(turn, config.delay).await;
+                let details = fetch_details(apartment, config).await;
+                scrape_progress += 1.0 / count as f64;
+                match details {
+                    Ok(details) => {
+                        apartment.details = Some(details);
+                        None
+                    }
+                    Err(error) => Some(ScrapeProblem {
+                        page: None,
+                        building: Some(name.clone()),
+                        field: "details".to_string(),
+                        reason: error.to_string(),
+                    }),
+                }
+            }
+        })
+        .buffer_unordered(config.concurrency.max(1))
+        .filter_map(|problem| async move { problem })
+        .collect()
+        .await
+}
+
+/// Knobs for fetching the result pages.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScrapeConfig {
+    /// Maximum number of pages fetched and geocoded at the same time.
+    pub concurrency: usize,
+    /// Minimum time between the starts of two requests to the portal.
+    pub delay: Duration,
+    /// Number of retries of a request failing with 429, 5xx or a dropped connection.
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each following one.
+    pub backoff: Duration,
+    /// Maximum number of result pages of a search, above which it is refused.
+    pub max_pages: usize,
+    /// Whether to reuse the pages saved by an interrupted search, instead of starting over.
+    pub resume: bool,
+}
+
+impl Default for ScrapeConfig {
+    fn default() -> Self {
+        ScrapeConfig {
+            concurrency: 4,
+            delay: Duration::from_millis(500),
+            retries: 3,
+            backoff: Duration::from_secs(1),
+            max_pages: 100,
+            resume: false,
+        }
+    }
+}
+
+/// Geocodes the buildings of one result page, keeping those that fail without coordinates.
+async fn geocode_page(
+    buildings: &mut [Building],
+    page: usize,
+    geocoder: &dyn Geocoder,
+) -> Vec<ScrapeProblem> {
+    let mut problems = vec![];
+    for building in buildings {
+        match geocode(&building.address, geocoder).await {
+            Ok(location) => {
+                building.coordinates = Some(location.coordinates);
+                building.precision = Some(location.precision);
+            }
+            Err(error) => {
+                tracing::warn!("{}: {error}", building.address);
+                problems.push(ScrapeProblem {
+                    page: Some(page),
+                    building: Some(building.name.clone()),
+                    field: "coordinates".to_string(),
+                    reason: error.to_string(),
+                });
+            }
+        }
+    }
+    problems
+}
+
+/// Scrapes all pages of `search`, skipping the buildings, apartments and pages that fail.
+///
+/// Each page is sent to `pages` as soon as it is geocoded, in whatever order they complete.
+/// Only a failure on the first page, which gives the number of pages, aborts the search.
 pub async fn scrape(
+    search: &SavedSearch,
+    config: &ScrapeConfig,
     mut scrape_progress: Signal<f64>,
-    geocode_request: RequestBuilder,
-) -> Result<Vec<Building>, Error> {
-    let url = backend::get_suumo_url().await?;
-    tracing::debug!("scraping {url}");
-    let url = format!("https://corsproxy.io/?url={url}");
-    // let url = format!("https://crossorigin.me/{url}");
-
-    let client = Client::new();
-    let request = client.get(url);
-
-    let html = request.try_clone().unwrap().send().await?.text().await?;
-    // let html = include_str!("../suumo.html");
-
-    let mut doc = Html::parse_document(&html);
-
-    let pagination_sel = Selector::parse("ol.pagination-parts")?;
-    let pages: usize = doc
-        .select(&pagination_sel)
-        .next()
-        .ok_or(Error::Scrape("pagination not found".to_string()))?
-        .child_elements()
-        .last()
-        .ok_or(Error::Scrape("last page not found".to_string()))?
-        .text()
-        .collect::<String>()
-        .parse()?;
-
-    let building_sel = Selector::parse("div.cassetteitem")?;
-    let name_sel = Selector::parse("div.cassetteitem_content-title")?;
-    let address_sel = Selector::parse("li.cassetteitem_detail-col1")?;
-    let apartment_sel = Selector::parse("tr.js-cassette_link")?;
-    let rent_sel = Selector::parse("span.cassetteitem_price--rent")?;
-    let fees_sel = Selector::parse("span.cassetteitem_price--administration")?;
-    let deposit_sel = Selector::parse("span.cassetteitem_price--deposit")?;
-    let key_money_sel = Selector::parse("span.cassetteitem_price--gratuity")?;
-    let kind_sel = Selector::parse("span.cassetteitem_madori")?;
-    let area_sel = Selector::parse("span.cassetteitem_menseki")?;
-    let plan_sel = Selector::parse("img.casssetteitem_other-thumbnail-img")?;
-    let url_sel = Selector::parse("a.cassetteitem_other-linktext")?;
-    let id_sel = Selector::parse("input#bukken_0")?;
+    geocoder: &dyn Geocoder,
+    pages_sender: &UnboundedSender<Vec<Building>>,
+) -> Result<(Vec<Building>, ScrapeReport), Error> {
+    let source = &PageSource::parse(&search.url);
+    tracing::debug!("scraping {source:?}");
 
-    let mut buildings = vec![];
+    // pages saved by an interrupted run of the same search are not fetched again
+    let mut saved: HashMap<usize, ScrapedPage> = if config.resume {
+        crate::backend::get_scraped_pages(search.url.clone())
+            .await?
+            .into_iter()
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    let first = match saved.remove(&1) {
+        Some(first) => (first, true),
+        None => (source.scrape_page(1, config).await?, false),
+    };
+    let mut pages = first.0.pages;
+    let mut first = Some(first);
+    let live = matches!(source, PageSource::Live(_));
+    let turn = Mutex::new(());
+    scrape_progress.set(0.0);
 
-    for page in 1..=pages {
-        scrape_progress.set(page as f64 / pages as f64);
-        tracing::debug!("Page {page} / {pages}");
-
-        if page > 1 {
-            let html = request
-                .try_clone()
-                .unwrap()
-                .query(&[("page", format!("{page}"))])
-                .send()
-                .await?
-                .text()
-                .await?;
-            doc = Html::parse_document(&html);
+    // the pagination may stop a few pages ahead with an ellipsis, so the count grows as the
+    // pages are scraped
+    let mut scraped: Vec<ScrapedPage> = vec![];
+    while scraped.len() < pages {
+        if pages > config.max_pages {
+            return Err(Error::Scrape(format!(
+                "the search has {pages} pages or more, over the maximum of {}: narrow it down or raise the maximum",
+                config.max_pages
+            )));
         }
 
-        for building in doc.select(&building_sel) {
-            let find = |sel, sel_name| {
-                Ok::<String, Error>(
-                    building
-                        .select(sel)
-                        .next()
-                        .ok_or(Error::Scrape(format!("{sel_name} not found")))?
-                        .text()
-                        .collect(),
-                )
-            };
-            let name: String = find(&name_sel, "title")?;
-            let address = find(&address_sel, "address")?;
-
-            let request = geocode_request.try_clone().unwrap();
-            let coordinates = geocode(&address, request).await?;
-
-            let mut apartments = vec![];
-
-            for apartment in building.select(&apartment_sel) {
-                let find = |sel, sel_name| {
-                    Ok::<String, Error>(
-                        apartment
-                            .select(sel)
-                            .next()
-                            .ok_or(Error::Scrape(format!("{name}: {sel_name} not found")))?
-                            .text()
-                            .collect(),
-                    )
+        // `buffered` keeps the results in page order, whatever order they complete in
+        let batch: Vec<ScrapedPage> = stream::iter(scraped.len() + 1..=pages)
+            .map(|page| {
+                // along with whether it was saved, and thus already geocoded
+                let known = match page {
+                    1 => first.take(),
+                    _ => saved.remove(&page).map(|scraped| (scraped, true)),
                 };
-                let rent = find(&rent_sel, "rent")?;
-                let fees = find(&fees_sel, "fees")?;
-                let fees = if fees == "-" { None } else { Some(fees) };
-                let deposit = find(&deposit_sel, "deposit")?;
-                let deposit = if deposit == "-" { None } else { Some(deposit) };
-                let key_money = find(&key_money_sel, "key_money")?;
-                let key_money = if key_money == "-" {
-                    None
-                } else {
-                    Some(key_money)
-                };
-                let kind = find(&kind_sel, "kind")?;
-                let area = find(&area_sel, "area")?;
-                let plan = apartment
-                    .select(&plan_sel)
-                    .next()
-                    .ok_or(Error::Scrape(format!("{name}: plan not found")))?
-                    .attr("rel")
-                    .ok_or(Error::Scrape(format!("{name}: rel not found")))?
-                    .to_string();
-                let url = apartment
-                    .select(&url_sel)
-                    .next()
-                    .ok_or(Error::Scrape(format!("{name}: url not found")))?
-                    .attr("href")
-                    .ok_or(Error::Scrape(format!("{name}: href not found")))?
-                    .to_string();
-                let url = format!("https://suumo.jp{url}");
-                let id = apartment
-                    .select(&id_sel)
-                    .next()
-                    .ok_or(Error::Scrape(format!("{name}: id not found")))?
-                    .attr("value")
-                    .ok_or(Error::Scrape(format!("{name}: value not found")))?
-                    .trim()
-                    .parse::<u64>()?;
-
-                apartments.push(Apartment {
-                    rent,
-                    fees,
-                    deposit,
-                    key_money,
-                    kind,
-                    area,
-                    plan,
-                    url,
-                    id,
+                let turn = &turn;
+                async move {
+                    let fetched = match known {
+                        Some(known) => Ok(known),
+                        None => {
+                            if live {
+                                pace(turn, config.delay).await;
+                            }
+                            let scraped = source.scrape_page(page, config).await;
+                            scraped.map(|scraped| (scraped, false))
+                        }
+                    };
+                    let scraped = match fetched {
+                        Ok((scraped, true)) => scraped,
+                        Ok((mut scraped, false)) => {
+                            let problems =
+                                geocode_page(&mut scraped.buildings, page, geocoder).await;
+                            scraped.problems.extend(problems);
+                            for building in &mut scraped.buildings {
+                                for apartment in &mut building.apartments {
+                                    apartment.searches = vec![search.name.clone()];
+                                }
+                            }
+                            let saving = crate::backend::save_scraped_page(
+                                search.url.clone(),
+                                page,
+                                scraped.clone(),
+                            );
+                            if let Err(error) = saving.await {
+                                tracing::warn!("page {page} not saved: {error}");
+                            }
+                            scraped
+                        }
+                        // failed pages are not saved, so that a resumed search tries them again
+                        Err(error) => {
+                            tracing::warn!("page {page}: {error}");
+                            ScrapedPage {
+                                pages,
+                                buildings: vec![],
+                                problems: vec![ScrapeProblem {
+                                    page: Some(page),
+                                    building: None,
+                                    field: "page".to_string(),
+                                    reason: error.to_string(),
+                                }],
+                            }
+                        }
+                    };
+                    // the receiving end may have stopped listening, the result is returned anyway
+                    let _ = pages_sender.unbounded_send(scraped.buildings.clone());
+                    tracing::debug!("Page {page} / {pages}");
+                    scrape_progress += 1.0 / pages as f64;
+                    Ok::<_, Error>(scraped)
+                }
+            })
+            .buffered(config.concurrency.max(1))
+            .try_collect()
+            .await?;
+
+        let done = scraped.len() + batch.len();
+        pages = batch.iter().map(|page| page.pages).fold(pages, usize::max);
+        scraped.extend(batch);
+        scrape_progress.set(done as f64 / pages as f64);
+    }
+
+    let mut buildings = vec![];
+    let mut report = ScrapeReport::default();
+    for page in scraped {
+        buildings.extend(page.buildings);
+        report.problems.extend(page.problems);
+    }
+    if report
+        .problems
+        .iter()
+        .any(|problem| problem.field == "page")
+    {
+        report.incomplete.push(search.name.clone());
+    }
+
+    Ok((deduplicate(buildings), report))
+}
+
+/// Scrapes the enabled `searches`, merging the buildings and units they have in common.
+///
+/// A search that fails altogether is reported, and the other ones are still kept. The pages
+/// are streamed to `pages_sender` as in [`scrape`], which is closed when all are done.
+pub async fn scrape_searches(
+    searches: &[SavedSearch],
+    config: &ScrapeConfig,
+    scrape_progress: Signal<f64>,
+    geocoder: &dyn Geocoder,
+    pages_sender: UnboundedSender<Vec<Building>>,
+) -> Result<(Vec<Building>, ScrapeReport), Error> {
+    let mut buildings = vec![];
+    let mut report = ScrapeReport::default();
+    for search in searches.iter().filter(|search| search.enabled) {
+        match scrape(search, config, scrape_progress, geocoder, &pages_sender).await {
+            Ok((found, found_report)) => {
+                buildings.extend(found);
+                report.problems.extend(found_report.problems);
+                report.incomplete.extend(found_report.incomplete);
+            }
+            Err(error) => {
+                tracing::warn!("search {}: {error}", search.name);
+                report.problems.push(ScrapeProblem {
+                    page: None,
+                    building: None,
+                    field: format!("search {}", search.name),
+                    reason: error.to_string(),
                 });
+                report.incomplete.push(search.name.clone());
             }
-
-            buildings.push(Building {
-                name,
-                address,
-                coordinates,
-                apartments,
-                times: HashMap::new(),
-            });
         }
     }
 
-    Ok(buildings)
+    Ok((deduplicate(buildings), report))
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "server")]
+    #[test]
+    fn reads_saved_page() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let scraped = super::read_saved_page(dir, "suumo.html".as_ref(), 1).unwrap();
+
+        assert_eq!(scraped.pages, 1);
+        assert_eq!(scraped.buildings.len(), 50);
+        assert!(scraped.problems.is_empty());
+
+        let path = dir.join("fixtures/suumo_single.html");
+        let scraped = super::read_saved_page(dir, &path, 1).unwrap();
+        assert_eq!(scraped.buildings.len(), 1);
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn reads_only_pages_under_dir() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+        for path in ["../suumo.html", "/etc/passwd", "missing.html"] {
+            assert!(
+                super::read_saved_page(&dir, path.as_ref(), 1).is_err(),
+                "{path}"
+            );
+        }
+    }
 }
@@ -0,0 +1,228 @@
+use std::fmt;
+
+/// Tokyo's special wards, by SUUMO `sc` code.
+pub const WARDS: [(&str, &str); 23] = [
+    ("13101", "千代田区"),
+    ("13102", "中央区"),
+    ("13103", "港区"),
+    ("13104", "新宿区"),
+    ("13105", "文京区"),
+    ("13106", "台東区"),
+    ("13107", "墨田区"),
+    ("13108", "江東区"),
+    ("13109", "品川区"),
+    ("13110", "目黒区"),
+    ("13111", "大田区"),
+    ("13112", "世田谷区"),
+    ("13113", "渋谷区"),
+    ("13114", "中野区"),
+    ("13115", "杉並区"),
+    ("13116", "豊島区"),
+    ("13117", "北区"),
+    ("13118", "荒川区"),
+    ("13119", "板橋区"),
+    ("13120", "練馬区"),
+    ("13121", "足立区"),
+    ("13122", "葛飾区"),
+    ("13123", "江戸川区"),
+];
+
+/// Floor plans, by SUUMO `md` code.
+pub const LAYOUTS: [(&str, &str); 14] = [
+    ("01", "ワンルーム"),
+    ("02", "1K"),
+    ("03", "1DK"),
+    ("04", "1LDK"),
+    ("05", "2K"),
+    ("06", "2DK"),
+    ("07", "2LDK"),
+    ("08", "3K"),
+    ("09", "3DK"),
+    ("10", "3LDK"),
+    ("11", "4K"),
+    ("12", "4DK"),
+    ("13", "4LDK"),
+    ("14", "5K以上"),
+];
+
+/// SUUMO's value for an open upper bound.
+const UNBOUNDED: &str = "9999999";
+
+/// A SUUMO search, as the query string of its results URL.
+///
+/// All parameters are kept in their original order, including the ones without typed
+/// accessors or without a value, so that a pasted URL is written back unchanged.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SearchQuery {
+    base: String,
+    params: Vec<(String, Option<String>)>,
+}
+
+impl SearchQuery {
+    pub fn parse(url: &str) -> Self {
+        let url = url.trim();
+        let (base, query) = url.split_once('?').unwrap_or((url, ""));
+        let params = query
+            .split('&')
+            .filter(|param| !param.is_empty())
+            .map(|param| match param.split_once('=') {
+                Some((key, value)) => (key.to_string(), Some(value.to_string())),
+                None => (param.to_string(), None),
+            })
+            .collect();
+        SearchQuery {
+            base: base.to_string(),
+            params,
+        }
+    }
+
+    fn values(&self, key: &str) -> Vec<&str> {
+        self.params
+            .iter()
+            .filter(|(k, _)| k == key)
+            .filter_map(|(_, value)| value.as_deref())
+            .collect()
+    }
+
+    fn value(&self, key: &str) -> Option<&str> {
+        self.values(key).into_iter().next()
+    }
+
+    /// Replaces all values of `key`, where its first value was, or at the end.
+    fn set_values(&mut self, key: &str, values: impl IntoIterator<Item = String>) {
+        let position = self
+            .params
+            .iter()
+            .position(|(k, _)| k == key)
+            .unwrap_or(self.params.len());
+        self.params.retain(|(k, _)| k != key);
+        let position = position.min(self.params.len());
+        self.params.splice(
+            position..position,
+            values
+                .into_iter()
+                .map(|value| (key.to_string(), Some(value))),
+        );
+    }
+
+    fn toggle(&mut self, key: &str, code: &str, enabled: bool) {
+        let mut values = self
+            .values(key)
+            .into_iter()
+            .filter(|value| *value != code)
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if enabled {
+            values.push(code.to_string());
+        }
+        self.set_values(key, values);
+    }
+
+    fn bound(&self, key: &str) -> Option<f64> {
+        self.value(key)
+            .filter(|value| *value != UNBOUNDED)
+            .and_then(|value| value.parse().ok())
+            .filter(|value| *value > 0.0)
+    }
+
+    /// `sc` ward codes.
+    pub fn wards(&self) -> Vec<&str> {
+        self.values("sc")
+    }
+
+    pub fn set_ward(&mut self, code: &str, enabled: bool) {
+        self.toggle("sc", code, enabled);
+    }
+
+    /// `md` layout codes.
+    pub fn layouts(&self) -> Vec<&str> {
+        self.values("md")
+    }
+
+    pub fn set_layout(&mut self, code: &str, enabled: bool) {
+        self.toggle("md", code, enabled);
+    }
+
+    /// `tc` option codes, such as "2nd floor or higher" or "separate bath and toilet".
+    pub fn options(&self) -> Vec<&str> {
+        self.values("tc")
+    }
+
+    /// Rent range in 万円, from `cb` and `ct`.
+    pub fn rent(&self) -> (Option<f64>, Option<f64>) {
+        (self.bound("cb"), self.bound("ct"))
+    }
+
+    pub fn set_rent(&mut self, min: Option<f64>, max: Option<f64>) {
+        self.set_values("cb", [format!("{:.1}", min.unwrap_or(0.0))]);
+        self.set_values(
+            "ct",
+            [max.map_or(UNBOUNDED.to_string(), |max| format!("{max:.1}"))],
+        );
+    }
+
+    /// Floor area range in square meters, from `mb` and `mt`.
+    pub fn area(&self) -> (Option<f64>, Option<f64>) {
+        (self.bound("mb"), self.bound("mt"))
+    }
+
+    pub fn set_area(&mut self, min: Option<f64>, max: Option<f64>) {
+        self.set_values("mb", [format!("{}", min.unwrap_or(0.0))]);
+        self.set_values(
+            "mt",
+            [max.map_or(UNBOUNDED.to_string(), |max| format!("{max}"))],
+        );
+    }
+}
+
+impl fmt::Display for SearchQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.base)?;
+        for (k, (key, value)) in self.params.iter().enumerate() {
+            let separator = if k == 0 { '?' } else { '&' };
+            match value {
+                Some(value) => write!(f, "{separator}{key}={value}")?,
+                None => write!(f, "{separator}{key}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SUUMOURL;
+
+    #[test]
+    fn writes_back_unchanged() {
+        assert_eq!(SearchQuery::parse(SUUMOURL).to_string(), SUUMOURL);
+        for url in [
+            "https://suumo.jp/jj/chintai/ichiran/FR301FC001/",
+            "https://suumo.jp/jj/chintai/ichiran/FR301FC001/?foo&fw2=&sc=13103",
+        ] {
+            assert_eq!(SearchQuery::parse(url).to_string(), url);
+        }
+    }
+
+    #[test]
+    fn sets_wards() {
+        let mut query = SearchQuery::parse(SUUMOURL);
+        query.set_ward("13101", true);
+        query.set_ward("13104", false);
+        assert_eq!(query.wards(), ["13103", "13113", "13110", "13112", "13101"]);
+        let expected = SUUMOURL
+            .replace("&sc=13104", "")
+            .replace("&sc=13112", "&sc=13112&sc=13101");
+        assert_eq!(query.to_string(), expected);
+    }
+
+    #[test]
+    fn sets_rent() {
+        let mut query = SearchQuery::parse(SUUMOURL);
+        query.set_rent(Some(8.0), None);
+        assert_eq!(query.rent(), (Some(8.0), None));
+        let expected = SUUMOURL.replace("&cb=0.0&ct=20.0", "&cb=8.0&ct=9999999");
+        assert_eq!(query.to_string(), expected);
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+
+use scraper::{ElementRef, Html, Selector};
+
+use super::{
+    FieldError, ListingSource, find_attr, find_text, new_building, parse_area, parse_charge,
+    parse_floor, parse_rent, parse_station_access, problem,
+};
+use crate::{Apartment, Building, Error, Layout, ListingStatus, ScrapeProblem};
+
+/// LIFULL HOME'S, whose result pages list buildings as `div.mod-mergeBuilding--rent--photo`.
+pub struct Homes;
+
+struct Selectors {
+    building: Selector,
+    name: Selector,
+    spec: Selector,
+    th: Selector,
+    td: Selector,
+    station: Selector,
+    apartment: Selector,
+    floor: Selector,
+    rent: Selector,
+    fees: Selector,
+    deposit: Selector,
+    key_money: Selector,
+    layout: Selector,
+    area: Selector,
+    plan: Selector,
+    url: Selector,
+}
+
+impl Selectors {
+    fn new() -> Result<Self, Error> {
+        Ok(Selectors {
+            building: Selector::parse("div.mod-mergeBuilding--rent--photo")?,
+            name: Selector::parse("span.bukkenName")?,
+            spec: Selector::parse("table.verticalTable tr")?,
+            th: Selector::parse("th")?,
+            td: Selector::parse("td")?,
+            station: Selector::parse("span.prg-stationText")?,
+            apartment: Selector::parse("tr.prg-unitRow")?,
+            floor: Selector::parse("td.roomKaisu")?,
+            rent: Selector::parse("span.priceLabel")?,
+            fees: Selector::parse("span.administration")?,
+            deposit: Selector::parse("span.deposit")?,
+            key_money: Selector::parse("span.keyMoney")?,
+            layout: Selector::parse("span.madori")?,
+            area: Selector::parse("span.menseki")?,
+            plan: Selector::parse("img.floorplanImage")?,
+            url: Selector::parse("a.prg-detailLink")?,
+        })
+    }
+}
+
+/// Apartment id from the 40 hex digits of a room URL, e.g. `/chintai/room/0a1b…/`.
+///
+/// Its first 15 digits fit in a `u64`, and are far from SUUMO's 12 digit decimal ids.
+fn room_id(url: &str) -> Option<u64> {
+    let (_, room) = url.split_once("/room/")?;
+    let room = room.trim_end_matches('/');
+    u64::from_str_radix(room.get(..15)?, 16).ok()
+}
+
+fn parse_apartment(sel: &Selectors, apartment: ElementRef) -> Result<Apartment, FieldError> {
+    let rent = parse_rent(&find_text(apartment, &sel.rent, "rent")?)?;
+    let price = |field, sel| parse_charge(field, &find_text(apartment, sel, field)?, &rent);
+    let fees = price("fees", &sel.fees)?;
+    let deposit = price("deposit", &sel.deposit)?;
+    let key_money = price("key_money", &sel.key_money)?;
+    let layout = find_text(apartment, &sel.layout, "layout")?
+        .trim()
+        .parse::<Layout>()
+        .map_err(|error| ("layout", error.to_string()))?;
+    let area = find_text(apartment, &sel.area, "area")?;
+    let area = parse_area(&area).ok_or(("area", format!("invalid {}", area.trim())))?;
+    let floor = parse_floor(&find_text(apartment, &sel.floor, "floor")?)?;
+    let plan = find_attr(apartment, &sel.plan, "src", "plan")?.to_string();
+    let url = find_attr(apartment, &sel.url, "href", "url")?;
+    let url = if url.starts_with('/') {
+        format!("https://www.homes.co.jp{url}")
+    } else {
+        url.to_string()
+    };
+    let id = room_id(&url).ok_or(("id", format!("invalid {url}")))?;
+
+    Ok(Apartment {
+        rent,
+        fees,
+        deposit,
+        key_money,
+        layout,
+        area,
+        floor,
+        plan,
+        url,
+        id,
+        status: ListingStatus::Unchanged,
+        details: None,
+        alternatives: vec![],
+        searches: vec![],
+    })
+}
+
+impl ListingSource for Homes {
+    fn name(&self) -> &'static str {
+        "LIFULL HOME'S"
+    }
+
+    fn handles(&self, url: &str) -> bool {
+        super::is_on(url, "homes.co.jp")
+    }
+
+    fn recognizes(&self, doc: &Html) -> bool {
+        Selectors::new().is_ok_and(|sel| doc.select(&sel.building).next().is_some())
+    }
+
+    /// The highest page number in the pagination, which is missing when there is one page.
+    fn page_count(&self, doc: &Html) -> Result<usize, Error> {
+        let pagination_sel = Selector::parse("div.mod-resultPaging li")?;
+        Ok(doc
+            .select(&pagination_sel)
+            .filter_map(|li| li.text().collect::<String>().trim().parse().ok())
+            .max()
+            .unwrap_or(1))
+    }
+
+    fn parse_page(
+        &self,
+        doc: &Html,
+        page: usize,
+    ) -> Result<(Vec<Building>, Vec<ScrapeProblem>), Error> {
+        let sel = Selectors::new()?;
+
+        let mut buildings = vec![];
+        let mut problems = vec![];
+
+        for building in doc.select(&sel.building) {
+            let name = match find_text(building, &sel.name, "title") {
+                Ok(name) => name.trim().to_string(),
+                Err(error) => {
+                    problems.push(problem(page, None, error));
+                    continue;
+                }
+            };
+
+            // the building is described by a table of `th`/`td` rows
+            let spec = building
+                .select(&sel.spec)
+                .filter_map(|row| {
+                    let th = row.select(&sel.th).next()?.text().collect::<String>();
+                    let td = row.select(&sel.td).next()?.text().collect::<String>();
+                    Some((th.trim().to_string(), td.trim().to_string()))
+                })
+                .collect::<HashMap<_, _>>();
+            let Some(address) = spec.get("所在地").cloned() else {
+                problems.push(problem(
+                    page,
+                    Some(&name),
+                    ("address", "not found".to_string()),
+                ));
+                continue;
+            };
+            let stations = building
+                .select(&sel.station)
+                .filter_map(|access| parse_station_access(&access.text().collect::<String>()))
+                .collect();
+            let age_floors = spec
+                .get("築年数/階数")
+                .map(|text| text.split('/').collect::<Vec<_>>())
+                .unwrap_or_default();
+
+            let mut apartments = vec![];
+
+            for apartment in building.select(&sel.apartment) {
+                match parse_apartment(&sel, apartment) {
+                    Ok(apartment) => apartments.push(apartment),
+                    Err(error) => problems.push(problem(page, Some(&name), error)),
+                }
+            }
+
+            if apartments.is_empty() {
+                continue;
+            }
+
+            buildings.push(new_building(
+                name,
+                address,
+                stations,
+                &age_floors,
+                apartments,
+            ));
+        }
+
+        Ok((buildings, problems))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../../fixtures/homes.html");
+
+    #[test]
+    fn parses_result_page() {
+        let doc = Html::parse_document(FIXTURE);
+        let (buildings, problems) = Homes.parse_page(&doc, 1).unwrap();
+
+        assert_eq!(buildings.len(), 2);
+        let building = &buildings[0];
+        assert_eq!(building.name, "パークアクシス西新宿");
+        assert_eq!(building.address, "東京都新宿区西新宿6");
+        assert_eq!(building.stations.len(), 3);
+        assert_eq!(building.stations[0].station, "西新宿");
+        assert_eq!(building.stations[0].walk, Some(6));
+        assert_eq!(building.stations[2].bus, Some(10));
+        assert_eq!(building.age(), Some(12));
+        assert_eq!(building.floors, Some(14));
+
+        let apartment = &building.apartments[0];
+        assert_eq!(apartment.rent.yen, 125_000);
+        assert_eq!(apartment.fees.as_ref().map(|fees| fees.yen), Some(8_000));
+        assert_eq!(
+            apartment.deposit.as_ref().map(|deposit| deposit.yen),
+            Some(125_000)
+        );
+        assert!(apartment.key_money.is_none());
+        assert_eq!(apartment.layout.to_string(), "1LDK");
+        assert_eq!(apartment.area, 40.5);
+        assert_eq!(apartment.floor.as_ref().map(|floor| floor.lowest), Some(3));
+        assert_eq!(apartment.id, 0x0a1b2c3d4e5f607);
+
+        let apartment = &building.apartments[1];
+        assert!(apartment.fees.is_none());
+        assert!(apartment.deposit.is_none());
+        assert!(
+            apartment
+                .url
+                .starts_with("https://www.homes.co.jp/chintai/room/")
+        );
+
+        // the unit without a rent is reported, and its neighbour kept
+        assert_eq!(buildings[1].age(), Some(0));
+        assert_eq!(buildings[1].apartments.len(), 1);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].field, "rent");
+        assert_eq!(problems[0].building.as_deref(), Some("メゾン中落合"));
+    }
+
+    #[test]
+    fn recognizes_result_page() {
+        let doc = Html::parse_document(FIXTURE);
+        assert!(Homes.recognizes(&doc));
+        assert!(!super::super::Suumo.recognizes(&doc));
+    }
+
+    #[test]
+    fn counts_pages() {
+        let doc = Html::parse_document(FIXTURE);
+        assert_eq!(Homes.page_count(&doc).unwrap(), 3);
+    }
+}
@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+
+use chrono::{Datelike, Local};
+use scraper::{ElementRef, Html, Selector};
+
+use crate::{Apartment, Building, Error, Floor, Price, ScrapeProblem, StationAccess};
+
+mod homes;
+mod suumo;
+
+pub use homes::Homes;
+pub use suumo::Suumo;
+
+/// A rental portal, whose result pages are parsed into buildings and apartments.
+pub trait ListingSource: Sync {
+    /// Name of the portal, as shown to the user.
+    fn name(&self) -> &'static str;
+
+    /// Whether `url` is a search or a listing on this portal.
+    fn handles(&self, url: &str) -> bool;
+
+    /// Whether `doc` is a result page of this portal, for saved pages without their URL.
+    fn recognizes(&self, doc: &Html) -> bool;
+
+    /// URL of the given result page of the search at `url`, in place of the page it is on.
+    fn page_url(&self, url: &str, page: usize) -> String {
+        let (base, query) = url.split_once('?').unwrap_or((url, ""));
+        let mut params = query
+            .split('&')
+            .filter(|param| !param.is_empty() && param.split('=').next() != Some("page"))
+            .collect::<Vec<_>>();
+        let page = format!("page={page}");
+        params.push(&page);
+        format!("{base}?{}", params.join("&"))
+    }
+
+    /// Number of pages of the search, read from any of its result pages.
+    fn page_count(&self, doc: &Html) -> Result<usize, Error>;
+
+    /// Parses the buildings of one result page, without their coordinates.
+    ///
+    /// Broken buildings and apartments are skipped, and reported as problems.
+    fn parse_page(
+        &self,
+        doc: &Html,
+        page: usize,
+    ) -> Result<(Vec<Building>, Vec<ScrapeProblem>), Error>;
+
+    /// Whether `parse_details` understands the detail pages of the apartments.
+    fn has_details(&self) -> bool {
+        false
+    }
+
+    /// Parses the detail page of an apartment.
+    fn parse_details(&self, _html: &str) -> Result<crate::ApartmentDetails, Error> {
+        Err(Error::Scrape(format!("no detail pages on {}", self.name())))
+    }
+}
+
+/// All supported portals.
+pub const SOURCES: [&dyn ListingSource; 2] = [&Suumo, &Homes];
+
+/// The portal of `url`, SUUMO unless another one handles it.
+pub fn source_for(url: &str) -> &'static dyn ListingSource {
+    supported_source(url).unwrap_or(&Suumo)
+}
+
+/// The portal of `url`, if any handles it, so that the server only fetches the portals.
+pub fn supported_source(url: &str) -> Option<&'static dyn ListingSource> {
+    SOURCES.into_iter().find(|source| source.handles(url))
+}
+
+/// Whether `url` is a web page on `domain` or one of its subdomains.
+fn is_on(url: &str, domain: &str) -> bool {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return false;
+    };
+    let host = url.host_str().unwrap_or_default();
+    matches!(url.scheme(), "http" | "https")
+        && (host == domain || host.ends_with(&format!(".{domain}")))
+}
+
+/// The portal of the saved result page `doc`, SUUMO unless another one recognizes it.
+pub fn source_of(doc: &Html) -> &'static dyn ListingSource {
+    SOURCES
+        .into_iter()
+        .find(|source| source.recognizes(doc))
+        .unwrap_or(&Suumo)
+}
+
+/// Parses one access line, e.g. `都営大江戸線/六本木駅 歩6分` or
+/// `ＪＲ山手線/渋谷駅 バス19分 (バス停)淡島 歩4分`, where other portals write `徒歩`.
+pub(crate) fn parse_station_access(text: &str) -> Option<StationAccess> {
+    let mut words = text.split_whitespace();
+    let route = words.next()?;
+    let (line, station) = route.split_once('/').unwrap_or(("", route));
+    let station = station.strip_suffix('駅').unwrap_or(station);
+
+    let minutes = |word: &str, prefix| word.strip_prefix(prefix)?.strip_suffix('分')?.parse().ok();
+    let (mut walk, mut bus) = (None, None);
+    for word in words {
+        if let Some(time) = minutes(word, "バス") {
+            bus = Some(time);
+        } else if let Some(time) = minutes(word, "歩").or_else(|| minutes(word, "徒歩")) {
+            walk = Some(time);
+        }
+    }
+
+    Some(StationAccess {
+        line: line.to_string(),
+        station: station.to_string(),
+        walk,
+        bus,
+    })
+}
+
+/// Parses the age column, e.g. `築20年`, or `新築` for new buildings.
+pub(crate) fn parse_age(text: &str) -> Option<usize> {
+    let text = text.trim();
+    if text == "新築" {
+        return Some(0);
+    }
+    text.strip_prefix('築')?.strip_suffix('年')?.parse().ok()
+}
+
+/// Parses the number of floors above ground, e.g. `15階建` or `地下1地上10階建`.
+pub(crate) fn parse_floors(text: &str) -> Option<usize> {
+    let text = text.trim().strip_suffix("階建")?;
+    let text = text.split_once("地上").map_or(text, |(_, above)| above);
+    text.parse().ok()
+}
+
+/// Parses a floor area, e.g. `25.35m2`.
+pub(crate) fn parse_area(text: &str) -> Option<f64> {
+    let text = text.trim();
+    text.strip_suffix("m2")
+        .or_else(|| text.strip_suffix("m²"))?
+        .parse()
+        .ok()
+}
+
+/// Parses a yen amount, e.g. `12.5万円` or `8000円`.
+pub(crate) fn parse_yen(text: &str) -> Option<u64> {
+    let text = text.trim().strip_suffix('円')?.replace(',', "");
+    match text.strip_suffix('万') {
+        Some(man) => Some((man.parse::<f64>().ok()? * 10_000.0).round() as u64),
+        None => text.parse().ok(),
+    }
+}
+
+/// Parses a multiple of the monthly rent, e.g. `1ヶ月`.
+pub(crate) fn parse_months(text: &str) -> Option<f64> {
+    let text = text.trim();
+    ["ヶ月", "ヵ月", "カ月", "か月"]
+        .iter()
+        .find_map(|suffix| text.strip_suffix(suffix)?.parse().ok())
+}
+
+/// Parses a listed amount, resolving multiples of the monthly rent against `rent`.
+pub(crate) fn parse_price(text: String, rent: u64) -> Option<Price> {
+    let (yen, months) = match parse_months(&text) {
+        Some(months) => ((rent as f64 * months).round() as u64, Some(months)),
+        None => (parse_yen(&text)?, None),
+    };
+    Some(Price { text, yen, months })
+}
+
+/// A field that could not be scraped, and why.
+pub(crate) type FieldError = (&'static str, String);
+
+pub(crate) fn find_text(
+    element: ElementRef,
+    sel: &Selector,
+    field: &'static str,
+) -> Result<String, FieldError> {
+    Ok(element
+        .select(sel)
+        .next()
+        .ok_or((field, "not found".to_string()))?
+        .text()
+        .collect())
+}
+
+pub(crate) fn find_attr<'a>(
+    element: ElementRef<'a>,
+    sel: &Selector,
+    attr: &str,
+    field: &'static str,
+) -> Result<&'a str, FieldError> {
+    element
+        .select(sel)
+        .next()
+        .ok_or((field, "not found".to_string()))?
+        .attr(attr)
+        .ok_or((field, format!("{attr} not found")))
+}
+
+/// The problem of a field of the building `name`, or of the building itself.
+pub(crate) fn problem(
+    page: usize,
+    name: Option<&str>,
+    (field, reason): FieldError,
+) -> ScrapeProblem {
+    ScrapeProblem {
+        page: Some(page),
+        building: name.map(str::to_string),
+        field: field.to_string(),
+        reason,
+    }
+}
+
+/// Parses the monthly rent, which is never given as a multiple of itself.
+pub(crate) fn parse_rent(text: &str) -> Result<Price, FieldError> {
+    let text = text.trim();
+    parse_price(text.to_string(), 0)
+        .filter(|rent| rent.months.is_none())
+        .ok_or(("rent", format!("invalid {text}")))
+}
+
+/// Parses an amount charged on top of `rent`, where `-` and `無` mean none.
+pub(crate) fn parse_charge(
+    field: &'static str,
+    text: &str,
+    rent: &Price,
+) -> Result<Option<Price>, FieldError> {
+    let text = text.trim();
+    if text == "-" || text == "無" {
+        return Ok(None);
+    }
+    parse_price(text.to_string(), rent.yen)
+        .map(Some)
+        .ok_or((field, format!("invalid {text}")))
+}
+
+/// Parses the floor of a unit, which is left blank or `-` when unknown.
+pub(crate) fn parse_floor(text: &str) -> Result<Option<Floor>, FieldError> {
+    match text.trim() {
+        "" | "-" => Ok(None),
+        floor => Ok(Some(
+            floor
+                .parse::<Floor>()
+                .map_err(|error| ("floor", error.to_string()))?,
+        )),
+    }
+}
+
+/// A building of a result page, not yet located nor routed, whose age and number of floors
+/// are found among `age_floors`.
+pub(crate) fn new_building(
+    name: String,
+    address: String,
+    stations: Vec<StationAccess>,
+    age_floors: &[impl AsRef<str>],
+    apartments: Vec<Apartment>,
+) -> Building {
+    let built = age_floors
+        .iter()
+        .find_map(|text| parse_age(text.as_ref()))
+        .map(|age| Local::now().year() - age as i32);
+    let floors = age_floors
+        .iter()
+        .find_map(|text| parse_floors(text.as_ref()));
+    Building {
+        name,
+        address,
+        stations,
+        built,
+        floors,
+        coordinates: None,
+        precision: None,
+        apartments,
+        times: HashMap::new(),
+        time_ranges: HashMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_yen() {
+        for (text, yen) in [
+            ("12.5万円", Some(125_000)),
+            ("8万円", Some(80_000)),
+            ("8,000円", Some(8_000)),
+            (" 8000円 ", Some(8_000)),
+            ("-", None),
+            ("8000", None),
+        ] {
+            assert_eq!(parse_yen(text), yen, "{text}");
+        }
+    }
+
+    #[test]
+    fn parses_months() {
+        for (text, months) in [
+            ("1ヶ月", Some(1.0)),
+            ("1.5ヵ月", Some(1.5)),
+            ("2カ月", Some(2.0)),
+            ("-", None),
+            ("1万円", None),
+        ] {
+            assert_eq!(parse_months(text), months, "{text}");
+        }
+    }
+
+    #[test]
+    fn parses_prices() {
+        let price = parse_price("1ヶ月".to_string(), 125_000).unwrap();
+        assert_eq!((price.yen, price.months), (125_000, Some(1.0)));
+        let price = parse_price("12.5万円".to_string(), 90_000).unwrap();
+        assert_eq!((price.yen, price.months), (125_000, None));
+        assert_eq!(parse_price("-".to_string(), 90_000), None);
+    }
+
+    #[test]
+    fn parses_charges() {
+        let rent = parse_rent(" 12.5万円 ").unwrap();
+        assert_eq!(rent.yen, 125_000);
+        assert!(parse_rent("1ヶ月").is_err());
+        for text in ["-", "無", " - "] {
+            assert_eq!(parse_charge("deposit", text, &rent), Ok(None));
+        }
+        let deposit = parse_charge("deposit", "1ヶ月", &rent).unwrap().unwrap();
+        assert_eq!(deposit.yen, 125_000);
+        assert!(parse_charge("deposit", "?", &rent).is_err());
+    }
+
+    #[test]
+    fn handles_only_portal_urls() {
+        let url = "https://suumo.jp/jj/chintai/ichiran/FR301FC001/?ar=030";
+        assert!(supported_source(url).is_some_and(|source| source.handles(url)));
+        assert!(supported_source("https://www.homes.co.jp/chintai/tokyo/list/").is_some());
+        for url in [
+            "http://localhost:8080/?suumo.jp",
+            "https://suumo.jp.example.com/",
+            "https://notsuumo.jp/",
+            "file:///suumo.jp/etc/passwd",
+            "suumo.jp",
+        ] {
+            assert!(supported_source(url).is_none(), "{url}");
+        }
+    }
+
+    #[test]
+    fn replaces_page_parameter() {
+        for (url, page_url) in [
+            (
+                "https://www.homes.co.jp/chintai/tokyo/shinjuku-city/list/",
+                "https://www.homes.co.jp/chintai/tokyo/shinjuku-city/list/?page=2",
+            ),
+            (
+                "https://www.homes.co.jp/chintai/tokyo/shinjuku-city/list/?page=3&cond[monthmoneyroomh]=15",
+                "https://www.homes.co.jp/chintai/tokyo/shinjuku-city/list/?cond[monthmoneyroomh]=15&page=2",
+            ),
+            (
+                "https://suumo.jp/jj/chintai/ichiran/FR301FC001/?ar=030&pc=50",
+                "https://suumo.jp/jj/chintai/ichiran/FR301FC001/?ar=030&pc=50&page=2",
+            ),
+        ] {
+            assert_eq!(source_for(url).page_url(url, 2), page_url);
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+
+use scraper::{ElementRef, Html, Selector};
+
+use super::{
+    FieldError, ListingSource, find_attr, find_text, new_building, parse_area, parse_charge,
+    parse_floor, parse_rent, parse_station_access, problem,
+};
+use crate::{Apartment, Building, Error, Layout, ListingStatus, ScrapeProblem};
+
+/// SUUMO, whose result pages list buildings as `div.cassetteitem`.
+pub struct Suumo;
+
+struct Selectors {
+    building: Selector,
+    name: Selector,
+    address: Selector,
+    station: Selector,
+    age_floors: Selector,
+    apartment: Selector,
+    floor: Selector,
+    rent: Selector,
+    fees: Selector,
+    deposit: Selector,
+    key_money: Selector,
+    layout: Selector,
+    area: Selector,
+    plan: Selector,
+    url: Selector,
+    id: Selector,
+}
+
+impl Selectors {
+    fn new() -> Result<Self, Error> {
+        Ok(Selectors {
+            building: Selector::parse("div.cassetteitem")?,
+            name: Selector::parse("div.cassetteitem_content-title")?,
+            address: Selector::parse("li.cassetteitem_detail-col1")?,
+            station: Selector::parse("li.cassetteitem_detail-col2 div.cassetteitem_detail-text")?,
+            age_floors: Selector::parse("li.cassetteitem_detail-col3 div")?,
+            apartment: Selector::parse("tr.js-cassette_link")?,
+            floor: Selector::parse("td:nth-child(3)")?,
+            rent: Selector::parse("span.cassetteitem_price--rent")?,
+            fees: Selector::parse("span.cassetteitem_price--administration")?,
+            deposit: Selector::parse("span.cassetteitem_price--deposit")?,
+            key_money: Selector::parse("span.cassetteitem_price--gratuity")?,
+            layout: Selector::parse("span.cassetteitem_madori")?,
+            area: Selector::parse("span.cassetteitem_menseki")?,
+            plan: Selector::parse("img.casssetteitem_other-thumbnail-img")?,
+            url: Selector::parse("a.cassetteitem_other-linktext")?,
+            id: Selector::parse("input#bukken_0")?,
+        })
+    }
+}
+
+fn parse_apartment(sel: &Selectors, apartment: ElementRef) -> Result<Apartment, FieldError> {
+    let rent = parse_rent(&find_text(apartment, &sel.rent, "rent")?)?;
+    let price = |field, sel| parse_charge(field, &find_text(apartment, sel, field)?, &rent);
+    let fees = price("fees", &sel.fees)?;
+    let deposit = price("deposit", &sel.deposit)?;
+    let key_money = price("key_money", &sel.key_money)?;
+    let layout = find_text(apartment, &sel.layout, "layout")?
+        .parse::<Layout>()
+        .map_err(|error| ("layout", error.to_string()))?;
+    let area = find_text(apartment, &sel.area, "area")?;
+    let area = parse_area(&area).ok_or(("area", format!("invalid {area}")))?;
+    let floor = parse_floor(&find_text(apartment, &sel.floor, "floor")?)?;
+    let plan = find_attr(apartment, &sel.plan, "rel", "plan")?.to_string();
+    let url = find_attr(apartment, &sel.url, "href", "url")?;
+    // saved pages have their links already made absolute
+    let url = if url.starts_with('/') {
+        format!("https://suumo.jp{url}")
+    } else {
+        url.to_string()
+    };
+    let id = find_attr(apartment, &sel.id, "value", "id")?;
+    let id = id
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| ("id", format!("invalid {id}")))?;
+
+    Ok(Apartment {
+        rent,
+        fees,
+        deposit,
+        key_money,
+        layout,
+        area,
+        floor,
+        plan,
+        url,
+        id,
+        status: ListingStatus::Unchanged,
+        details: None,
+        alternatives: vec![],
+        searches: vec![],
+    })
+}
+
+/// The `page` parameter of a result page URL.
+fn page_param(url: &str) -> Option<usize> {
+    let (_, query) = url.split_once('?')?;
+    query
+        .split('&')
+        .find_map(|param| param.strip_prefix("page=")?.parse().ok())
+}
+
+impl ListingSource for Suumo {
+    fn name(&self) -> &'static str {
+        "SUUMO"
+    }
+
+    fn handles(&self, url: &str) -> bool {
+        super::is_on(url, "suumo.jp")
+    }
+
+    fn recognizes(&self, doc: &Html) -> bool {
+        Selectors::new().is_ok_and(|sel| doc.select(&sel.building).next().is_some())
+    }
+
+    /// The highest page in the pagination or behind the "next" link.
+    ///
+    /// When the pagination ends with an ellipsis instead of the last page, this is only the
+    /// highest page shown so far, and the following pages tell more.
+    fn page_count(&self, doc: &Html) -> Result<usize, Error> {
+        let item_sel = Selector::parse("ol.pagination-parts li")?;
+        let link_sel = Selector::parse("p.pagination-parts a")?;
+        let items = doc
+            .select(&item_sel)
+            .filter_map(|li| li.text().collect::<String>().trim().parse().ok());
+        let links = doc
+            .select(&link_sel)
+            .filter_map(|a| page_param(a.attr("href")?));
+        // a search that fits on one page has no pagination
+        Ok(items.chain(links).max().unwrap_or(1))
+    }
+
+    fn parse_page(
+        &self,
+        doc: &Html,
+        page: usize,
+    ) -> Result<(Vec<Building>, Vec<ScrapeProblem>), Error> {
+        let sel = Selectors::new()?;
+
+        let mut buildings = vec![];
+        let mut problems = vec![];
+
+        for building in doc.select(&sel.building) {
+            let name = match find_text(building, &sel.name, "title") {
+                Ok(name) => name,
+                Err(error) => {
+                    problems.push(problem(page, None, error));
+                    continue;
+                }
+            };
+            let address = match find_text(building, &sel.address, "address") {
+                Ok(address) => address,
+                Err(error) => {
+                    problems.push(problem(page, Some(&name), error));
+                    continue;
+                }
+            };
+            let stations = building
+                .select(&sel.station)
+                .filter_map(|access| parse_station_access(&access.text().collect::<String>()))
+                .collect();
+            let age_floors = building
+                .select(&sel.age_floors)
+                .map(|div| div.text().collect::<String>())
+                .collect::<Vec<_>>();
+
+            let mut apartments = vec![];
+
+            for apartment in building.select(&sel.apartment) {
+                match parse_apartment(&sel, apartment) {
+                    Ok(apartment) => apartments.push(apartment),
+                    Err(error) => problems.push(problem(page, Some(&name), error)),
+                }
+            }
+
+            if apartments.is_empty() {
+                continue;
+            }
+
+            buildings.push(new_building(
+                name,
+                address,
+                stations,
+                &age_floors,
+                apartments,
+            ));
+        }
+
+        Ok((buildings, problems))
+    }
+
+    fn has_details(&self) -> bool {
+        true
+    }
+
+    fn parse_details(&self, html: &str) -> Result<crate::ApartmentDetails, Error> {
+        let doc = Html::parse_document(html);
+        let row_sel = Selector::parse("tr")?;
+        let th_sel = Selector::parse("th")?;
+        let td_sel = Selector::parse("td")?;
+        let facilities_sel = Selector::parse("#bkdt-option li")?;
+        let photo_sel = Selector::parse("img")?;
+
+        // the attributes are spread over several tables of `th`/`td` pairs
+        let mut attributes = HashMap::new();
+        for row in doc.select(&row_sel) {
+            for (th, td) in row.select(&th_sel).zip(row.select(&td_sel)) {
+                let key = th.text().collect::<String>().trim().to_string();
+                let value = td.text().collect::<String>().trim().to_string();
+                attributes.entry(key).or_insert(value);
+            }
+        }
+        let attribute = |key: &str| {
+            attributes
+                .get(key)
+                .filter(|value| !value.is_empty())
+                .cloned()
+        };
+
+        let lease = attribute("契約期間").and_then(|term| {
+            if term.contains("定期借家") {
+                Some(crate::Lease::FixedTerm)
+            } else if term.contains("普通借家") {
+                Some(crate::Lease::Regular)
+            } else {
+                None
+            }
+        });
+
+        let facilities = doc
+            .select(&facilities_sel)
+            .flat_map(|li| {
+                li.text()
+                    .collect::<String>()
+                    .split('、')
+                    .map(|facility| facility.trim().to_string())
+                    .filter(|facility| !facility.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        let mut photos: Vec<String> = vec![];
+        for img in doc.select(&photo_sel) {
+            let src = img.attr("data-src").or(img.attr("src")).unwrap_or_default();
+            if src.contains("/front/gazo/fr/bukken/") && !photos.iter().any(|photo| photo == src) {
+                photos.push(src.to_string());
+            }
+        }
+
+        Ok(crate::ApartmentDetails {
+            orientation: attribute("向き"),
+            move_in: attribute("入居"),
+            lease,
+            facilities,
+            photos,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../../suumo.html");
+
+    #[test]
+    fn parses_result_page() {
+        let doc = Html::parse_document(FIXTURE);
+        let (buildings, problems) = Suumo.parse_page(&doc, 1).unwrap();
+
+        assert_eq!(buildings.len(), 50);
+        assert!(problems.is_empty());
+        assert!(
+            buildings
+                .iter()
+                .all(|building| !building.apartments.is_empty())
+        );
+        assert!(
+            buildings
+                .iter()
+                .all(|building| !building.stations.is_empty())
+        );
+        let apartment = &buildings[0].apartments[0];
+        assert!(apartment.url.starts_with("https://suumo.jp/"));
+        assert!(apartment.rent.yen > 0);
+    }
+
+    #[test]
+    fn recognizes_result_page() {
+        let doc = Html::parse_document(FIXTURE);
+        assert!(Suumo.recognizes(&doc));
+        assert!(!super::super::Homes.recognizes(&doc));
+    }
+
+    fn page_count(html: &str) -> usize {
+        let doc = Html::parse_document(html);
+        assert_eq!(Suumo.parse_page(&doc, 1).unwrap().0.len(), 1);
+        Suumo.page_count(&doc).unwrap()
+    }
+
+    #[test]
+    fn counts_pages() {
+        let doc = Html::parse_document(FIXTURE);
+        assert_eq!(Suumo.page_count(&doc).unwrap(), 23);
+    }
+
+    #[test]
+    fn counts_single_page() {
+        assert_eq!(
+            page_count(include_str!("../../fixtures/suumo_single.html")),
+            1
+        );
+    }
+
+    #[test]
+    fn counts_pages_from_next_link() {
+        assert_eq!(
+            page_count(include_str!("../../fixtures/suumo_next.html")),
+            2
+        );
+    }
+
+    #[test]
+    fn counts_pages_before_trailing_ellipsis() {
+        assert_eq!(
+            page_count(include_str!("../../fixtures/suumo_ellipsis.html")),
+            5
+        );
+    }
+
+    const DETAILS: &str = include_str!("../../fixtures/suumo_details.html");
+
+    #[test]
+    fn parses_details() {
+        let details = Suumo.parse_details(DETAILS).unwrap();
+
+        assert_eq!(details.orientation.as_deref(), Some("南東"));
+        assert_eq!(details.move_in.as_deref(), Some("即入居可"));
+        assert_eq!(details.lease, Some(crate::Lease::FixedTerm));
+        assert_eq!(
+            details.facilities,
+            [
+                "バストイレ別",
+                "エアコン",
+                "室内洗濯機置場",
+                "フローリング",
+                "オートロック",
+                "宅配ボックス",
+                "南東向き",
+                "2沿線利用可",
+            ]
+        );
+        assert!(details.has_facility("バス・トイレ別"));
+        // the gallery repeats the main photo, and the logo and lazy placeholders are skipped
+        let photos = "https://img01.suumo.com/front/gazo/fr/bukken/394/100452150394/100452150394";
+        assert_eq!(
+            details.photos,
+            [
+                format!("{photos}_gw.jpg"),
+                format!("{photos}_co.jpg"),
+                format!("{photos}_r1.jpg"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_leases() {
+        // only the contract term tells, whatever the notes mention
+        let lease = |html: String| Suumo.parse_details(&html).unwrap().lease;
+        assert_eq!(lease(DETAILS.to_string()), Some(crate::Lease::FixedTerm));
+        assert_eq!(
+            lease(
+                DETAILS
+                    .replace("定期借家 2年", "普通借家 2年")
+                    .replace("普通借家への切替不可", "定期借家への切替あり")
+            ),
+            Some(crate::Lease::Regular)
+        );
+        assert_eq!(lease(DETAILS.replace("定期借家 ", "")), None);
+    }
+
+    #[test]
+    fn skips_empty_details() {
+        let details = Suumo
+            .parse_details(&DETAILS.replace("即入居可", "").replace("南東\n", ""))
+            .unwrap();
+        assert_eq!(details.move_in, None);
+        assert_eq!(details.orientation, None);
+    }
+}